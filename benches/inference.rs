@@ -0,0 +1,181 @@
+//! Times one inference of a drone-sized controller and of a lookup table baked
+//! from it, checking that neither makes heap allocations, then the same
+//! controller in batches. Run with `cargo bench`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use fuzzy::{
+    funcs::{gbell, smf, zmf},
+    fuzzy::Fuzzy,
+    lut::Lut,
+    mamdani::Mamdani,
+    partition::{Partition, Shape},
+    rules::{Diff, InputType, Inputs, Outputs, Th, X},
+    snapshot::Snapshot,
+    variables::{Term, Var},
+};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const RULES: &str = "
+    IF Vx IS P AND Th IS N AND X IS N THEN Diff IS PM
+    IF Vx IS P AND Th IS Z AND X IS Z THEN Diff IS PS
+    IF Vx IS P AND Th IS P AND X IS P THEN Diff IS NS
+    IF Vx IS N AND Th IS N AND X IS N THEN Diff IS PS
+    IF Vx IS N AND Th IS Z AND X IS N THEN Diff IS NL
+    IF Vx IS N AND Th IS P AND X IS Z THEN Diff IS NM
+    IF Vx IS P AND Th IS Z AND X IS P THEN Diff IS PL
+    IF X IS Z AND Th IS Z THEN Diff IS Z
+    IF W IS P THEN Diff IS NS WITH 0.5
+    IF W IS N THEN Diff IS PS WITH 0.5
+";
+
+fn controller() -> Mamdani {
+    let even = |var: InputType, range: std::ops::Range<f32>| {
+        let terms = Partition::even(Shape::Smooth, &range, 2)
+            .unwrap()
+            .terms(var);
+        (var, Fuzzy::new(var, terms, range))
+    };
+    let (vx, w) = (even(InputType::Vx, -4.0..4.), even(InputType::W, -0.6..0.6));
+    let x: [(Term, _); 3] = [
+        (Inputs::X(X::N).into(), zmf(-10., 8.)),
+        (Inputs::X(X::Z).into(), gbell(4., 1.5, 0.)),
+        (Inputs::X(X::P).into(), smf(-8., 10.)),
+    ];
+    let th: [(Term, _); 3] = [
+        (Inputs::Th(Th::N).into(), zmf(-0.5, 0.4)),
+        (Inputs::Th(Th::Z).into(), gbell(0.15, 2., 0.)),
+        (Inputs::Th(Th::P).into(), smf(-0.4, 0.5)),
+    ];
+    let diff = [
+        (Diff::NL, -10.),
+        (Diff::NM, -4.),
+        (Diff::NS, -2.),
+        (Diff::Z, 0.),
+        (Diff::PS, 2.),
+        (Diff::PM, 4.),
+        (Diff::PL, 10.),
+    ]
+    .map(|(d, c)| (Term::from(Outputs::Diff(d)), gbell(1.6, 3., c)));
+    Mamdani::builder()
+        .input(vx.0, vx.1)
+        .input(w.0, w.1)
+        .input(InputType::X, Fuzzy::new(InputType::X, x, -10.0..10.))
+        .input(InputType::Th, Fuzzy::new(InputType::Th, th, -0.5..0.5))
+        .output(Fuzzy::new("Diff", diff, -10.0..10.))
+        .text_rules(RULES)
+        .build()
+        .unwrap()
+}
+
+fn main() {
+    let m = controller();
+    let vars = [InputType::X, InputType::Vx, InputType::Th, InputType::W].map(Var::from);
+    let states: Vec<[f32; 4]> = (0..1000)
+        .map(|i| {
+            let t = i as f32 * 0.01;
+            [
+                12. * t.sin(),
+                4. * (t * 1.3).cos(),
+                0.6 * (t * 0.7).sin(),
+                0.5 * t.cos(),
+            ]
+        })
+        .collect();
+    let mut inputs = vars.map(|v| (v, 0.));
+    let mut snapshot = Snapshot::new();
+    time("Mamdani::infer", &states, &mut inputs, |i| {
+        m.infer(i, &mut snapshot).unwrap()
+    });
+    let lut = Lut::bake(&m, 9).unwrap();
+    time("Lut::eval", &states, &mut inputs, |i| lut.eval(i).unwrap());
+    println!("Lut of 9^4 points: {}", lut.report(&m, 17).unwrap());
+
+    let columns: Vec<Vec<f32>> = (0..4)
+        .map(|d| {
+            (0..100)
+                .flat_map(|_| states.iter().map(move |s| s[d]))
+                .collect()
+        })
+        .collect();
+    let batch: Vec<(Var, &[f32])> = inputs
+        .iter()
+        .zip(&columns)
+        .map(|((v, _), c)| (v.clone(), &c[..]))
+        .collect();
+    let mut out = vec![0.; columns[0].len()];
+    for threads in [1, 4] {
+        let start = Instant::now();
+        m.infer_batch_parallel(&batch, &mut out, threads, &mut snapshot)
+            .unwrap();
+        println!(
+            "Mamdani::infer_batch on {} threads: {:.3} us per row",
+            threads,
+            start.elapsed().as_secs_f64() * 1e6 / out.len() as f64
+        );
+    }
+}
+
+/// Runs `infer` over every state a hundred times, after one warm-up call.
+fn time(
+    name: &str,
+    states: &[[f32; 4]],
+    inputs: &mut [(Var, f32)],
+    mut infer: impl FnMut(&[(Var, f32)]) -> f32,
+) {
+    let mut run = |state: &[f32; 4]| {
+        for ((_, x), s) in inputs.iter_mut().zip(state) {
+            *x = *s;
+        }
+        black_box(infer(black_box(inputs)));
+    };
+    run(&states[0]);
+
+    let rounds = 100;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for state in states {
+            run(state);
+        }
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    let n = rounds * states.len();
+    println!(
+        "{}: {:.3} us per inference, {} allocations in {} inferences",
+        name,
+        elapsed.as_secs_f64() * 1e6 / n as f64,
+        allocations,
+        n
+    );
+    assert_eq!(allocations, 0, "{} allocated", name);
+}
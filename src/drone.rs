@@ -1,14 +1,63 @@
 #![allow(non_snake_case)]
 
+use crate::error::FuzzyError;
+use crate::lut::Lut;
 use crate::mamdani::Mamdani;
-use crate::{rules::InputType, state::State};
+use crate::snapshot::Snapshot;
+use crate::sugeno::Sugeno;
+use crate::trace::Trace;
+use crate::{rules::InputType, state::State, variables::Var};
 use macroquad::prelude::*;
 use macroquad_particles::Emitter;
 
+pub trait Controller {
+    /// Crisp output for the crisp inputs, recording what happened in `snapshot`.
+    fn infer(&self, inputs: &[(Var, f32)], snapshot: &mut Snapshot) -> Result<f32, FuzzyError>;
+
+    /// Like [`infer`](Self::infer), with a trace if the controller can explain itself.
+    fn infer_explained(
+        &self,
+        inputs: &[(Var, f32)],
+        snapshot: &mut Snapshot,
+    ) -> Result<(f32, Option<Trace>), FuzzyError> {
+        Ok((self.infer(inputs, snapshot)?, None))
+    }
+}
+
+impl Controller for Mamdani {
+    fn infer(&self, inputs: &[(Var, f32)], snapshot: &mut Snapshot) -> Result<f32, FuzzyError> {
+        Mamdani::infer(self, inputs, snapshot)
+    }
+
+    fn infer_explained(
+        &self,
+        inputs: &[(Var, f32)],
+        snapshot: &mut Snapshot,
+    ) -> Result<(f32, Option<Trace>), FuzzyError> {
+        Mamdani::infer_explained(self, inputs, snapshot).map(|(x, trace)| (x, Some(trace)))
+    }
+}
+
+impl Controller for Lut {
+    fn infer(&self, inputs: &[(Var, f32)], _: &mut Snapshot) -> Result<f32, FuzzyError> {
+        self.eval(inputs)
+    }
+}
+
+impl Controller for Sugeno {
+    fn infer(&self, inputs: &[(Var, f32)], snapshot: &mut Snapshot) -> Result<f32, FuzzyError> {
+        Sugeno::infer(self, inputs, snapshot)
+    }
+}
+
 pub struct Drone {
     pub enable: bool,
     pub state: State,
     pub steps: i32,
+    /// Reasoning of both controllers in the last step of the last update.
+    pub traces: (Option<Trace>, Option<Trace>),
+    /// What both controllers saw and did while flying this drone.
+    pub snapshots: (Snapshot, Snapshot),
     m: f32,
     M: f32,
     t_m: f32,
@@ -37,6 +86,8 @@ impl Drone {
             Tr: 0.,
             state: State::default(),
             steps: 5,
+            traces: (None, None),
+            snapshots: (Snapshot::new(), Snapshot::new()),
             enable: true,
             smoke1: e1,
             smoke2: e2,
@@ -47,14 +98,14 @@ impl Drone {
         }
     }
 
-    pub fn update(&mut self, controller: &mut Mamdani, controller2: &mut Mamdani, dt: f32) {
+    pub fn update(&mut self, controller: &impl Controller, controller2: &impl Controller, dt: f32) {
         let steps = if dt > 0.02 {
             ((self.steps * 60) as f32 * dt) as i32
         } else {
             self.steps
         };
         let dt = dt / steps as f32;
-        for _ in 0..steps {
+        for step in 0..steps {
             // self.error = PI - self.state.th;
             // self.int += self.error * dt;
             // self.F = 0.;
@@ -90,23 +141,39 @@ impl Drone {
             //     .output(self.state.p.x - self.point.x, dt)
             //     .clamp(-0.8, 0.8);
             // let _diff = self.pid3.output(o1 - self.state.th, dt).clamp(-10., 10.);
-            let _amp = controller.infer(&[
-                (InputType::Y, self.state.p.y - self.point.y),
-                (InputType::Vy, self.state.v.y),
-            ]);
-            let _diff = controller2.infer(&[
-                (InputType::X, self.state.p.x - self.point.x),
-                (InputType::Vx, self.state.v.x),
-                (InputType::Th, self.state.th),
-                (InputType::W, self.state.w),
-            ]);
+            let amp_inputs = [
+                (InputType::Y.into(), self.state.p.y - self.point.y),
+                (InputType::Vy.into(), self.state.v.y),
+            ];
+            let diff_inputs = [
+                (InputType::X.into(), self.state.p.x - self.point.x),
+                (InputType::Vx.into(), self.state.v.x),
+                (InputType::Th.into(), self.state.th),
+                (InputType::W.into(), self.state.w),
+            ];
+            let (_amp, _diff) = if step + 1 == steps {
+                let amp = controller.infer_explained(&amp_inputs, &mut self.snapshots.0);
+                let diff = controller2.infer_explained(&diff_inputs, &mut self.snapshots.1);
+                let (amp, amp_trace) = amp.map_or_else(|e| (Err(e), None), |(x, t)| (Ok(x), t));
+                let (diff, diff_trace) = diff.map_or_else(|e| (Err(e), None), |(x, t)| (Ok(x), t));
+                self.traces = (amp_trace, diff_trace);
+                (amp, diff)
+            } else {
+                (
+                    controller.infer(&amp_inputs, &mut self.snapshots.0),
+                    controller2.infer(&diff_inputs, &mut self.snapshots.1),
+                )
+            };
             // let _diff = 0.0;
             // .clamp(-10., 10.);
             // dbg!(&_diff);
             // self.state.w = self.state.w.clamp(-0.2, 0.2);
             // println!("y: {} \t t: {}", self.state.x.y, t);
-            self.Tl = self.t_m * (_amp - _diff).max(0.);
-            self.Tr = self.t_m * (_amp + _diff).max(0.);
+            // Without an output from either controller the thrusters stay off
+            if let (Ok(_amp), Ok(_diff)) = (_amp, _diff) {
+                self.Tl = self.t_m * (_amp - _diff).max(0.);
+                self.Tr = self.t_m * (_amp + _diff).max(0.);
+            }
             self.smoke1.config.amount = (self.Tl * 0.5) as u32;
             self.smoke2.config.amount = (self.Tr * 0.5) as u32;
             let k1 = self.process_state(&self.state);
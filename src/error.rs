@@ -0,0 +1,99 @@
+use std::{fmt::Display, ops::Range};
+
+use crate::{
+    rules::Norm,
+    variables::{Term, Var},
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum FuzzyError {
+    /// A crisp value was given for a variable without membership functions.
+    UnknownInput(Var),
+    /// A rule needs a variable that was not given a crisp value.
+    MissingInput(Var),
+    /// A rule uses an input term without a membership function.
+    MissingMembership(Term),
+    /// A rule concludes an output term without a membership function.
+    MissingOutput(Term),
+    /// A linear consequent weighs a variable that is not an input of the controller.
+    UnknownConsequentInput(Var),
+    /// An output term is not the consequent of any rule.
+    UncoveredOutput(Term),
+    /// A Mamdani output term is a singleton, which the sampled output set would miss.
+    SingletonOutput(Term),
+    /// A variable was given twice to a builder, defined twice in a registry, or is
+    /// two axes of a lookup table.
+    DuplicateVariable(Var),
+    /// A variable was given two membership functions for the same term.
+    DuplicateTerm(Var, Term),
+    /// No output variable was given to the builder.
+    MissingOutputVariable,
+    /// The universe of a variable is empty, reversed or not finite.
+    InvalidRange(String, Range<f32>),
+    /// A crisp value lies outside the range of a variable set to [`OutOfRange::Flag`](crate::fuzzy::OutOfRange).
+    OutOfRange(String, f32, Range<f32>),
+    /// A column of a batch has a different number of values than the output.
+    ColumnLength(Var, usize, usize),
+    /// The centers of a [`Partition`](crate::partition::Partition) are not
+    /// distinct and finite, or its names do not match them.
+    InvalidPartition(String),
+    /// An axis of a [`Lut`](crate::lut::Lut) was given fewer than two grid points.
+    TooFewPoints(Var, usize),
+    /// A rule weight lies outside [0, 1].
+    InvalidWeight(f32),
+    /// A Hamacher or Yager norm has a parameter outside its domain.
+    InvalidNorm(Norm),
+    /// A rule tree node has the wrong number of children.
+    MalformedRule(String),
+    /// A text rule could not be parsed, at `(line, column)`.
+    Parse(usize, usize, String),
+    /// No rule fired and the controller is set to [`NoFiring::Error`](crate::mamdani::NoFiring).
+    NoRuleFired,
+}
+
+impl Display for FuzzyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FuzzyError::UnknownInput(i) => write!(f, "{} not found in memberships", i),
+            FuzzyError::MissingInput(i) => write!(f, "{} not found in crisp inputs", i),
+            FuzzyError::MissingMembership(t) => {
+                write!(f, "no membership function for {} of {}", t, t.var())
+            }
+            FuzzyError::MissingOutput(t) => {
+                write!(f, "no membership function for output {} of {}", t, t.var())
+            }
+            FuzzyError::UnknownConsequentInput(v) => {
+                write!(f, "consequent uses {}, which is not an input", v)
+            }
+            FuzzyError::UncoveredOutput(t) => {
+                write!(f, "output {} of {} is not used by any rule", t, t.var())
+            }
+            FuzzyError::SingletonOutput(t) => {
+                write!(f, "output {} of {} is a singleton", t, t.var())
+            }
+            FuzzyError::DuplicateVariable(v) => write!(f, "variable {} is defined twice", v),
+            FuzzyError::TooFewPoints(v, n) => {
+                write!(f, "{} has {} grid points, fewer than two", v, n)
+            }
+            FuzzyError::DuplicateTerm(v, t) => write!(f, "term {} of {} is defined twice", t, v),
+            FuzzyError::MissingOutputVariable => write!(f, "no output variable"),
+            FuzzyError::InvalidRange(v, r) => {
+                write!(f, "invalid range {}..{} for {}", r.start, r.end, v)
+            }
+            FuzzyError::OutOfRange(v, x, r) => {
+                write!(f, "{} = {} is outside {}..{}", v, x, r.start, r.end)
+            }
+            FuzzyError::ColumnLength(v, n, rows) => {
+                write!(f, "column of {} has {} values for {} rows", v, n, rows)
+            }
+            FuzzyError::InvalidPartition(e) => write!(f, "invalid partition: {}", e),
+            FuzzyError::InvalidWeight(w) => write!(f, "rule weight {} is not in [0, 1]", w),
+            FuzzyError::InvalidNorm(n) => write!(f, "invalid parameter for {}", n),
+            FuzzyError::MalformedRule(e) => write!(f, "malformed rule: {}", e),
+            FuzzyError::Parse(line, col, e) => write!(f, "line {}, column {}: {}", line, col, e),
+            FuzzyError::NoRuleFired => write!(f, "no rule fired"),
+        }
+    }
+}
+
+impl std::error::Error for FuzzyError {}
@@ -1,79 +1,591 @@
-use std::rc::Rc;
-
-pub fn tri(a: f32, p: f32, b: f32) -> Rc<dyn Fn(f32) -> f32> {
-    Rc::new(move |x| {
-        if x < a {
-            0.
-        } else if x < p {
-            (x - a) / (p - a)
-        } else if x < b {
-            (b - x) / (b - p)
-        } else {
-            0.
-        }
-    })
+use std::{fmt::Display, ops::Range};
+
+/// A membership function, as its shape and parameters.
+///
+/// [`Fuzzy`](crate::fuzzy::Fuzzy) takes these in the units of its universe and
+/// evaluates them on [`normalized`](Mf::normalized) copies over [0, 1].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mf {
+    /// Rises from `a` to a peak at `b`, falls back to zero at `c`.
+    Tri { a: f32, b: f32, c: f32 },
+    /// Rises from `a` to `b`, stays at one until `c`, falls back to zero at `d`.
+    Trap { a: f32, b: f32, c: f32, d: f32 },
+    /// One before `a`, falling linearly to zero at `b`.
+    Cliff { a: f32, b: f32 },
+    /// Zero before `a`, rising linearly to one at `b`.
+    Mount { a: f32, b: f32 },
+    /// Smooth step from one at `a` down to zero at `b`.
+    Z { a: f32, b: f32 },
+    /// Smooth step from zero at `a` up to one at `b`.
+    S { a: f32, b: f32 },
+    /// Bell curve centered on `c` with standard deviation `sigma`.
+    Gauss { c: f32, sigma: f32 },
+    /// `1 / (1 + |(x - c) / a|^2b)`: half-width `a`, slope `b` and center `c`.
+    GBell { a: f32, b: f32, c: f32 },
+    /// `1 / (1 + e^(-a(x - c)))`, rising through one half at `c`, falling if `a` is negative.
+    Sigmoid { a: f32, c: f32 },
+    /// Difference of two sigmoids, `sig(a1, c1) - sig(a2, c2)`, clamped to [0, 1].
+    DSig { a1: f32, c1: f32, a2: f32, c2: f32 },
+    /// Product of two sigmoids, `sig(a1, c1) * sig(a2, c2)`.
+    PSig { a1: f32, c1: f32, a2: f32, c2: f32 },
+    /// S-shaped rise from `a` to `b`, then Z-shaped fall from `c` to `d`.
+    Pi { a: f32, b: f32, c: f32, d: f32 },
+    /// Left half of a Gaussian up to `c1`, one until `c2`, right half of another after it.
+    Gauss2 {
+        c1: f32,
+        sigma1: f32,
+        c2: f32,
+        sigma2: f32,
+    },
+    /// One exactly at `c` and zero everywhere else. Only for inputs: Mamdani
+    /// outputs are sampled, which would almost never hit `c`, so their builder rejects it.
+    Singleton { c: f32 },
+    /// Straight lines through `(x, y)` points sorted by `x`, flat beyond the first and last.
+    Piecewise(Vec<(f32, f32)>),
 }
 
-pub fn cliff(a: f32, b: f32) -> Rc<dyn Fn(f32) -> f32> {
-    Rc::new(move |x| {
-        if x < a {
-            1.
-        } else if x < b {
-            (b - x) / (b - a)
-        } else {
-            0.
+impl Mf {
+    pub fn eval(&self, x: f32) -> f32 {
+        let sig = |a: f32, c: f32| 1. / (1. + (-a * (x - c)).exp());
+        let half_gauss = |c: f32, sigma: f32| (-(x - c).powi(2) / (2. * sigma * sigma)).exp();
+        match *self {
+            Mf::Tri { a, b, c } => {
+                if x < a {
+                    0.
+                } else if x < b {
+                    (x - a) / (b - a)
+                } else if x < c {
+                    (c - x) / (c - b)
+                } else {
+                    0.
+                }
+            }
+            Mf::Trap { a, b, c, d } => {
+                if x < a {
+                    0.
+                } else if x < b {
+                    (x - a) / (b - a)
+                } else if x <= c {
+                    1.
+                } else if x < d {
+                    (d - x) / (d - c)
+                } else {
+                    0.
+                }
+            }
+            Mf::Cliff { a, b } => {
+                if x < a {
+                    1.
+                } else if x < b {
+                    (b - x) / (b - a)
+                } else {
+                    0.
+                }
+            }
+            Mf::Mount { a, b } => {
+                if x < a {
+                    0.
+                } else if x < b {
+                    (x - a) / (b - a)
+                } else {
+                    1.
+                }
+            }
+            Mf::Z { a, b } => 1. - Mf::S { a, b }.eval(x),
+            Mf::S { a, b } => {
+                if x < a {
+                    0.
+                } else if x < (a + b) / 2. {
+                    2. * (x - a) * (x - a) / ((b - a) * (b - a))
+                } else if x < b {
+                    1. - 2. * (x - b) * (x - b) / ((b - a) * (b - a))
+                } else {
+                    1.
+                }
+            }
+            Mf::Gauss { c, sigma } => half_gauss(c, sigma),
+            Mf::GBell { a, b, c } => 1. / (1. + ((x - c) / a).abs().powf(2. * b)),
+            Mf::Sigmoid { a, c } => sig(a, c),
+            Mf::DSig { a1, c1, a2, c2 } => (sig(a1, c1) - sig(a2, c2)).clamp(0., 1.),
+            Mf::PSig { a1, c1, a2, c2 } => sig(a1, c1) * sig(a2, c2),
+            Mf::Pi { a, b, c, d } => Mf::S { a, b }.eval(x) * Mf::Z { a: c, b: d }.eval(x),
+            Mf::Gauss2 {
+                c1,
+                sigma1,
+                c2,
+                sigma2,
+            } => {
+                if x < c1 {
+                    half_gauss(c1, sigma1)
+                } else if x <= c2 {
+                    1.
+                } else {
+                    half_gauss(c2, sigma2)
+                }
+            }
+            Mf::Singleton { c } => {
+                if x == c {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            Mf::Piecewise(ref points) => {
+                let Some(&(x0, y0)) = points.first() else {
+                    return 0.;
+                };
+                if x <= x0 {
+                    return y0;
+                }
+                for w in points.windows(2) {
+                    let ((xa, ya), (xb, yb)) = (w[0], w[1]);
+                    if x <= xb {
+                        return if xb > xa {
+                            ya + (yb - ya) * (x - xa) / (xb - xa)
+                        } else {
+                            yb
+                        };
+                    }
+                }
+                points[points.len() - 1].1
+            }
         }
-    })
-}
+    }
+
+    /// The same function over `range` mapped onto [0, 1].
+    pub fn normalized(&self, range: &Range<f32>) -> Mf {
+        self.rescale(range.start, range.end - range.start)
+    }
+
+    /// The inverse of [`normalized`](Self::normalized), back in the units of `range`.
+    pub fn in_units(&self, range: &Range<f32>) -> Mf {
+        let span = range.end - range.start;
+        self.rescale(-range.start / span, 1. / span)
+    }
+
+    /// Maps positions `p` to `(p - offset) / scale`, scaling widths and slopes to match.
+    fn rescale(&self, offset: f32, scale: f32) -> Mf {
+        let p = |p: f32| (p - offset) / scale;
+        let w = |w: f32| w / scale;
+        let k = |k: f32| k * scale;
+        match *self {
+            Mf::Tri { a, b, c } => Mf::Tri {
+                a: p(a),
+                b: p(b),
+                c: p(c),
+            },
+            Mf::Trap { a, b, c, d } => Mf::Trap {
+                a: p(a),
+                b: p(b),
+                c: p(c),
+                d: p(d),
+            },
+            Mf::Cliff { a, b } => Mf::Cliff { a: p(a), b: p(b) },
+            Mf::Mount { a, b } => Mf::Mount { a: p(a), b: p(b) },
+            Mf::Z { a, b } => Mf::Z { a: p(a), b: p(b) },
+            Mf::S { a, b } => Mf::S { a: p(a), b: p(b) },
+            Mf::Gauss { c, sigma } => Mf::Gauss {
+                c: p(c),
+                sigma: w(sigma),
+            },
+            Mf::GBell { a, b, c } => Mf::GBell {
+                a: w(a),
+                b,
+                c: p(c),
+            },
+            Mf::Sigmoid { a, c } => Mf::Sigmoid { a: k(a), c: p(c) },
+            Mf::DSig { a1, c1, a2, c2 } => Mf::DSig {
+                a1: k(a1),
+                c1: p(c1),
+                a2: k(a2),
+                c2: p(c2),
+            },
+            Mf::PSig { a1, c1, a2, c2 } => Mf::PSig {
+                a1: k(a1),
+                c1: p(c1),
+                a2: k(a2),
+                c2: p(c2),
+            },
+            Mf::Pi { a, b, c, d } => Mf::Pi {
+                a: p(a),
+                b: p(b),
+                c: p(c),
+                d: p(d),
+            },
+            Mf::Gauss2 {
+                c1,
+                sigma1,
+                c2,
+                sigma2,
+            } => Mf::Gauss2 {
+                c1: p(c1),
+                sigma1: w(sigma1),
+                c2: p(c2),
+                sigma2: w(sigma2),
+            },
+            Mf::Singleton { c } => Mf::Singleton { c: p(c) },
+            Mf::Piecewise(ref points) => {
+                Mf::Piecewise(points.iter().map(|&(x, y)| (p(x), y)).collect())
+            }
+        }
+    }
+
+    /// Name of the shape, e.g. `tri`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Mf::Tri { .. } => "tri",
+            Mf::Trap { .. } => "trap",
+            Mf::Cliff { .. } => "cliff",
+            Mf::Mount { .. } => "mount",
+            Mf::Z { .. } => "zmf",
+            Mf::S { .. } => "smf",
+            Mf::Gauss { .. } => "gauss",
+            Mf::GBell { .. } => "gbell",
+            Mf::Sigmoid { .. } => "sigmf",
+            Mf::DSig { .. } => "dsigmf",
+            Mf::PSig { .. } => "psigmf",
+            Mf::Pi { .. } => "pimf",
+            Mf::Gauss2 { .. } => "gauss2mf",
+            Mf::Singleton { .. } => "singleton",
+            Mf::Piecewise(_) => "piecewise",
+        }
+    }
+
+    /// Names of the parameters, in the order of [`params`](Self::params).
+    pub fn param_names(&self) -> Vec<String> {
+        let names: &[&str] = match self {
+            Mf::Tri { .. } | Mf::GBell { .. } => &["a", "b", "c"],
+            Mf::Trap { .. } | Mf::Pi { .. } => &["a", "b", "c", "d"],
+            Mf::Cliff { .. } | Mf::Mount { .. } | Mf::Z { .. } | Mf::S { .. } => &["a", "b"],
+            Mf::Gauss { .. } => &["c", "sigma"],
+            Mf::Sigmoid { .. } => &["a", "c"],
+            Mf::DSig { .. } | Mf::PSig { .. } => &["a1", "c1", "a2", "c2"],
+            Mf::Gauss2 { .. } => &["c1", "sigma1", "c2", "sigma2"],
+            Mf::Singleton { .. } => &["c"],
+            Mf::Piecewise(points) => {
+                return (0..points.len())
+                    .flat_map(|i| [format!("x{}", i), format!("y{}", i)])
+                    .collect()
+            }
+        };
+        names.iter().map(|n| n.to_string()).collect()
+    }
 
-pub fn mount(a: f32, b: f32) -> Rc<dyn Fn(f32) -> f32> {
-    Rc::new(move |x| {
-        if x < a {
-            0.
-        } else if x < b {
-            (x - a) / (b - a)
-        } else {
-            1.
+    pub fn params(&self) -> Vec<f32> {
+        match *self {
+            Mf::Tri { a, b, c } | Mf::GBell { a, b, c } => vec![a, b, c],
+            Mf::Trap { a, b, c, d } | Mf::Pi { a, b, c, d } => vec![a, b, c, d],
+            Mf::Cliff { a, b } | Mf::Mount { a, b } | Mf::Z { a, b } | Mf::S { a, b } => {
+                vec![a, b]
+            }
+            Mf::Gauss { c, sigma } => vec![c, sigma],
+            Mf::Sigmoid { a, c } => vec![a, c],
+            Mf::DSig { a1, c1, a2, c2 } | Mf::PSig { a1, c1, a2, c2 } => vec![a1, c1, a2, c2],
+            Mf::Gauss2 {
+                c1,
+                sigma1,
+                c2,
+                sigma2,
+            } => vec![c1, sigma1, c2, sigma2],
+            Mf::Singleton { c } => vec![c],
+            Mf::Piecewise(ref points) => points.iter().flat_map(|&(x, y)| [x, y]).collect(),
         }
-    })
-}
-
-pub fn zmf(a: f32, b: f32) -> Rc<dyn Fn(f32) -> f32> {
-    Rc::new(move |x| {
-        if x < a {
-            1.
-        } else if x < (a + b) / 2. {
-            1. - 2. * (x - a) * (x - a) / ((b - a) * (b - a))
-        } else if x < b {
-            2. * (x - b) * (x - b) / ((b - a) * (b - a))
-        } else {
-            0.
+    }
+
+    /// The same shape with new parameters, or `None` if their number is wrong.
+    pub fn with_params(&self, params: &[f32]) -> Option<Mf> {
+        if let Mf::Piecewise(points) = self {
+            return (params.len() == 2 * points.len())
+                .then(|| Mf::Piecewise(params.chunks(2).map(|p| (p[0], p[1])).collect()));
         }
-    })
-}
-
-pub fn smf(a: f32, b: f32) -> Rc<dyn Fn(f32) -> f32> {
-    Rc::new(move |x| {
-        if x < a {
-            0.
-        } else if x < (a + b) / 2. {
-            2. * (x - a) * (x - a) / ((b - a) * (b - a))
-        } else if x < b {
-            1. - 2. * (x - b) * (x - b) / ((b - a) * (b - a))
-        } else {
-            1.
+        Some(match (self, params) {
+            (Mf::Tri { .. }, &[a, b, c]) => Mf::Tri { a, b, c },
+            (Mf::Trap { .. }, &[a, b, c, d]) => Mf::Trap { a, b, c, d },
+            (Mf::Cliff { .. }, &[a, b]) => Mf::Cliff { a, b },
+            (Mf::Mount { .. }, &[a, b]) => Mf::Mount { a, b },
+            (Mf::Z { .. }, &[a, b]) => Mf::Z { a, b },
+            (Mf::S { .. }, &[a, b]) => Mf::S { a, b },
+            (Mf::Gauss { .. }, &[c, sigma]) => Mf::Gauss { c, sigma },
+            (Mf::GBell { .. }, &[a, b, c]) => Mf::GBell { a, b, c },
+            (Mf::Sigmoid { .. }, &[a, c]) => Mf::Sigmoid { a, c },
+            (Mf::DSig { .. }, &[a1, c1, a2, c2]) => Mf::DSig { a1, c1, a2, c2 },
+            (Mf::PSig { .. }, &[a1, c1, a2, c2]) => Mf::PSig { a1, c1, a2, c2 },
+            (Mf::Pi { .. }, &[a, b, c, d]) => Mf::Pi { a, b, c, d },
+            (Mf::Gauss2 { .. }, &[c1, sigma1, c2, sigma2]) => Mf::Gauss2 {
+                c1,
+                sigma1,
+                c2,
+                sigma2,
+            },
+            (Mf::Singleton { .. }, &[c]) => Mf::Singleton { c },
+            _ => return None,
+        })
+    }
+}
+
+impl Display for Mf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}(", self.kind())?;
+        for (i, (name, p)) in self.param_names().iter().zip(self.params()).enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", name, p)?;
         }
-    })
+        write!(f, ")")
+    }
+}
+
+pub fn tri(a: f32, p: f32, b: f32) -> Mf {
+    Mf::Tri { a, b: p, c: b }
+}
+
+pub fn trap(a: f32, b: f32, c: f32, d: f32) -> Mf {
+    Mf::Trap { a, b, c, d }
+}
+
+pub fn cliff(a: f32, b: f32) -> Mf {
+    Mf::Cliff { a, b }
+}
+
+pub fn mount(a: f32, b: f32) -> Mf {
+    Mf::Mount { a, b }
+}
+
+pub fn zmf(a: f32, b: f32) -> Mf {
+    Mf::Z { a, b }
+}
+
+pub fn smf(a: f32, b: f32) -> Mf {
+    Mf::S { a, b }
+}
+
+/// Gaussian spanning roughly `a..b`, centered between them.
+pub fn gauss(a: f32, b: f32) -> Mf {
+    Mf::Gauss {
+        c: (a + b) / 2.,
+        sigma: (b - a) / (2. * std::f32::consts::SQRT_2),
+    }
+}
+
+pub fn gbell(a: f32, b: f32, c: f32) -> Mf {
+    Mf::GBell { a, b, c }
+}
+
+pub fn sigmf(a: f32, c: f32) -> Mf {
+    Mf::Sigmoid { a, c }
 }
 
-pub fn gauss(a: f32, b: f32) -> Rc<dyn Fn(f32) -> f32> {
-    Rc::new(move |x| {
-        let c = (a + b) / 2.;
-        let d = (b - a) / 2.;
-        (-((x - c) / d).powi(2)).exp()
-    })
+pub fn dsigmf(a1: f32, c1: f32, a2: f32, c2: f32) -> Mf {
+    Mf::DSig { a1, c1, a2, c2 }
 }
 
-pub fn gbell(a: f32, b: f32, c: f32) -> Rc<dyn Fn(f32) -> f32> {
-    Rc::new(move |x| 1. / (1. + ((x - c) / a).abs().powi(2 * b as i32)))
+pub fn psigmf(a1: f32, c1: f32, a2: f32, c2: f32) -> Mf {
+    Mf::PSig { a1, c1, a2, c2 }
+}
+
+pub fn pimf(a: f32, b: f32, c: f32, d: f32) -> Mf {
+    Mf::Pi { a, b, c, d }
+}
+
+pub fn gauss2mf(c1: f32, sigma1: f32, c2: f32, sigma2: f32) -> Mf {
+    Mf::Gauss2 {
+        c1,
+        sigma1,
+        c2,
+        sigma2,
+    }
+}
+
+pub fn singleton(c: f32) -> Mf {
+    Mf::Singleton { c }
+}
+
+/// Sorts the points by `x`.
+pub fn piecewise(points: impl IntoIterator<Item = (f32, f32)>) -> Mf {
+    let mut points: Vec<_> = points.into_iter().collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Mf::Piecewise(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    fn assert_shape(mf: &Mf, points: &[(f32, f32)]) {
+        for &(x, y) in points {
+            assert_near(mf.eval(x), y);
+        }
+    }
+
+    #[test]
+    fn tri_peaks_at_middle_parameter() {
+        let mf = tri(0.2, 0.4, 0.8);
+        assert_shape(
+            &mf,
+            &[(0., 0.), (0.3, 0.5), (0.4, 1.), (0.6, 0.5), (0.9, 0.)],
+        );
+    }
+
+    #[test]
+    fn trap_is_flat_between_shoulders() {
+        let mf = trap(0., 0.2, 0.6, 1.);
+        assert_shape(
+            &mf,
+            &[
+                (0.1, 0.5),
+                (0.2, 1.),
+                (0.4, 1.),
+                (0.6, 1.),
+                (0.8, 0.5),
+                (1., 0.),
+            ],
+        );
+    }
+
+    #[test]
+    fn cliff_and_mount_are_mirrored_ramps() {
+        assert_shape(&cliff(0.2, 0.6), &[(0., 1.), (0.4, 0.5), (0.8, 0.)]);
+        assert_shape(&mount(0.2, 0.6), &[(0., 0.), (0.4, 0.5), (0.8, 1.)]);
+    }
+
+    #[test]
+    fn zmf_and_smf_are_complements() {
+        let (z, s) = (zmf(0.2, 0.8), smf(0.2, 0.8));
+        assert_shape(
+            &s,
+            &[(0., 0.), (0.35, 0.125), (0.5, 0.5), (0.65, 0.875), (1., 1.)],
+        );
+        for x in [0., 0.1, 0.3, 0.5, 0.7, 0.9] {
+            assert_near(z.eval(x) + s.eval(x), 1.);
+        }
+    }
+
+    #[test]
+    fn gauss_spans_its_range() {
+        let mf = gauss(0.2, 0.6);
+        assert_shape(
+            &mf,
+            &[(0.4, 1.), (0.2, (-1f32).exp()), (0.6, (-1f32).exp())],
+        );
+        assert_near(mf.eval(0.3), mf.eval(0.5));
+    }
+
+    #[test]
+    fn gbell_uses_a_real_exponent() {
+        let mf = gbell(0.2, 1.25, 0.5);
+        assert_shape(&mf, &[(0.5, 1.), (0.3, 0.5), (0.7, 0.5)]);
+        // |x - c| / a = 2, so 1 / (1 + 2^2.5)
+        assert_near(mf.eval(0.9), 1. / (1. + 2f32.powf(2.5)));
+    }
+
+    #[test]
+    fn sigmf_crosses_half_at_center() {
+        let mf = sigmf(10., 0.5);
+        assert_shape(&mf, &[(0.5, 0.5), (0.6, 1. / (1. + (-1f32).exp()))]);
+        assert!(mf.eval(0.) < 0.01 && mf.eval(1.) > 0.99);
+        assert!(sigmf(-10., 0.5).eval(0.) > 0.99);
+    }
+
+    #[test]
+    fn dsigmf_is_a_bump() {
+        let mf = dsigmf(50., 0.3, 50., 0.7);
+        assert_shape(&mf, &[(0., 0.), (0.5, 1.), (1., 0.)]);
+        assert_near(mf.eval(0.3), 0.5);
+    }
+
+    #[test]
+    fn psigmf_is_a_bump() {
+        let mf = psigmf(50., 0.3, -50., 0.7);
+        assert_shape(&mf, &[(0., 0.), (0.5, 1.), (1., 0.)]);
+        assert_near(mf.eval(0.7), 0.5);
+    }
+
+    #[test]
+    fn pimf_rises_holds_and_falls() {
+        let mf = pimf(0., 0.2, 0.6, 1.);
+        assert_shape(
+            &mf,
+            &[
+                (0., 0.),
+                (0.1, 0.5),
+                (0.2, 1.),
+                (0.4, 1.),
+                (0.8, 0.5),
+                (1., 0.),
+            ],
+        );
+    }
+
+    #[test]
+    fn gauss2mf_has_independent_sides() {
+        let mf = gauss2mf(0.3, 0.1, 0.5, 0.2);
+        let e = (-0.5f32).exp();
+        assert_shape(&mf, &[(0.2, e), (0.3, 1.), (0.4, 1.), (0.5, 1.), (0.7, e)]);
+    }
+
+    #[test]
+    fn singleton_is_one_only_at_its_point() {
+        let mf = singleton(0.5);
+        assert_shape(&mf, &[(0.5, 1.), (0.49, 0.), (0.51, 0.)]);
+    }
+
+    #[test]
+    fn piecewise_interpolates_sorted_points() {
+        let mf = piecewise([(0.5, 1.), (0.2, 0.), (1., 0.5)]);
+        assert_eq!(mf, Mf::Piecewise(vec![(0.2, 0.), (0.5, 1.), (1., 0.5)]));
+        assert_shape(
+            &mf,
+            &[(0., 0.), (0.35, 0.5), (0.5, 1.), (0.75, 0.75), (1.2, 0.5)],
+        );
+        assert_eq!(piecewise([]).eval(0.5), 0.);
+    }
+
+    #[test]
+    fn params_round_trip() {
+        let mfs = [
+            tri(0., 0.5, 1.),
+            trap(0., 0.2, 0.6, 1.),
+            gauss(0.2, 0.6),
+            gbell(0.2, 1.5, 0.5),
+            dsigmf(50., 0.3, 50., 0.7),
+            gauss2mf(0.3, 0.1, 0.5, 0.2),
+            singleton(0.5),
+            piecewise([(0., 0.), (1., 1.)]),
+        ];
+        for mf in mfs {
+            let params = mf.params();
+            assert_eq!(params.len(), mf.param_names().len());
+            assert_eq!(mf.with_params(&params), Some(mf.clone()));
+            assert_eq!(mf.with_params(&params[1..]), None);
+        }
+        assert_eq!(tri(0., 0.5, 1.).to_string(), "tri(a=0, b=0.5, c=1)");
+    }
+
+    #[test]
+    fn normalizing_keeps_the_shape() {
+        let range = -10.0..30.;
+        let mfs = [
+            tri(-10., 0., 20.),
+            gauss(0., 10.),
+            gbell(4., 1.5, 10.),
+            sigmf(0.5, 5.),
+            gauss2mf(0., 2., 10., 5.),
+            piecewise([(-5., 0.), (5., 1.)]),
+        ];
+        for mf in mfs {
+            let normalized = mf.normalized(&range);
+            for x in [-10., -4., 0., 3., 7.5, 12., 30.] {
+                assert_near(normalized.eval((x + 10.) / 40.), mf.eval(x));
+            }
+            let back = normalized.in_units(&range);
+            for (a, b) in back.params().into_iter().zip(mf.params()) {
+                assert_near(a, b);
+            }
+        }
+    }
 }
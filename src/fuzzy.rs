@@ -1,108 +1,505 @@
-use std::{collections::HashMap, fmt::Display, hash::Hash, ops::Range, rc::Rc};
+use std::{collections::HashMap, fmt::Display, hash::Hash, ops::Range};
 
-use egui_macroquad::egui::Context;
+use crate::{
+    error::FuzzyError,
+    funcs::Mf,
+    mamdani::{Aggregation, Implication},
+};
 
-use crate::ui::Graph;
+/// How the aggregated output set of a variable is reduced to a crisp value.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Defuzzifier {
+    Centroid,
+    Bisector,
+    MeanOfMax,
+    SmallestOfMax,
+    LargestOfMax,
+    /// Peaks of the terms, weighted by their firing strengths.
+    WeightedAverage,
+}
+
+impl Defuzzifier {
+    pub const ALL: [Defuzzifier; 6] = [
+        Defuzzifier::Centroid,
+        Defuzzifier::Bisector,
+        Defuzzifier::MeanOfMax,
+        Defuzzifier::SmallestOfMax,
+        Defuzzifier::LargestOfMax,
+        Defuzzifier::WeightedAverage,
+    ];
+}
+
+impl Display for Defuzzifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Defuzzifier::Centroid => write!(f, "Centroid"),
+            Defuzzifier::Bisector => write!(f, "Bisector"),
+            Defuzzifier::MeanOfMax => write!(f, "MOM"),
+            Defuzzifier::SmallestOfMax => write!(f, "SOM"),
+            Defuzzifier::LargestOfMax => write!(f, "LOM"),
+            Defuzzifier::WeightedAverage => write!(f, "WtAvg"),
+        }
+    }
+}
+
+/// What [`Fuzzy::fuzzify`] does with a crisp value outside the range.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum OutOfRange {
+    /// Fuzzify the nearest end of the range instead.
+    Clamp,
+    /// Fully in the term with the lowest or highest peak, and in no other.
+    Saturate,
+    /// Evaluate the membership functions past the range as they are.
+    Extrapolate,
+    /// Return [`FuzzyError::OutOfRange`].
+    Flag,
+}
 
+#[derive(Clone)]
 pub struct Fuzzy<V>
 where
-    V: Eq + Hash + Copy + Display,
+    V: Eq + Hash + Clone + Display,
 {
+    name: String,
+    unit: String,
     members: usize,
-    pub functions: HashMap<V, Rc<dyn Fn(f32) -> f32>>,
+    /// Terms in order from lowest to highest.
+    terms: Vec<V>,
+    /// Position of each term in `terms`.
+    index: HashMap<V, usize>,
+    /// Terms given more than once, once each.
+    duplicates: Vec<V>,
+    /// Membership functions of the terms in order, normalized over the range,
+    /// see [`Mf::normalized`].
+    functions: Vec<Mf>,
+    pub defuzzifier: Defuzzifier,
+    pub out_of_range: OutOfRange,
+    /// Normalized middle of the maximum of each term in order.
+    peaks: Vec<f32>,
+    /// Each function at the `resolution` points of the output curve, term after term.
+    samples: Vec<f32>,
     range: Range<f32>,
-    graph: Graph,
-    last_input: f32,
-    last_output: Vec<(f32, f32)>,
     resolution: usize,
 }
 
 impl<V> Fuzzy<V>
 where
-    V: Eq + Hash + Copy + Display,
+    V: Eq + Hash + Clone + Display,
 {
-    pub fn new(
+    /// Takes the membership functions in the same units as `range`, and the
+    /// order of the terms from the order they are given in.
+    pub fn new<K: Into<V>>(
         input_type: impl Display,
-        functions: HashMap<V, Rc<dyn Fn(f32) -> f32>>,
+        functions: impl IntoIterator<Item = (K, Mf)>,
         range: Range<f32>,
     ) -> Fuzzy<V> {
-        // let f = Rc::new(functions);
-        let mut titles: Vec<(String, Rc<dyn Fn(f32) -> f32>)> = functions
+        let functions: Vec<(V, Mf)> = functions
+            .into_iter()
+            .map(|(k, f)| (k.into(), f.normalized(&range)))
+            .collect();
+        // A term given twice keeps its first place and its last function, and is
+        // reported by the controllers built from it
+        let mut terms = Vec::with_capacity(functions.len());
+        let mut index = HashMap::with_capacity(functions.len());
+        let mut duplicates = Vec::new();
+        let mut mfs: Vec<Mf> = Vec::with_capacity(functions.len());
+        for (x, f) in functions {
+            match index.get(&x) {
+                Some(&i) => {
+                    if !duplicates.contains(&x) {
+                        duplicates.push(x);
+                    }
+                    mfs[i] = f;
+                }
+                None => {
+                    index.insert(x.clone(), terms.len());
+                    terms.push(x);
+                    mfs.push(f);
+                }
+            }
+        }
+        let functions = mfs;
+        let resolution = 100;
+        let samples: Vec<f32> = functions
             .iter()
-            .map(|(&x, y)| (x.to_string(), Rc::clone(y)))
+            .flat_map(|f| (0..resolution).map(|i| f.eval(i as f32 / (resolution - 1) as f32)))
+            .collect();
+        // The middle of the samples at the maximum, so that shoulders and
+        // trapezoids are not pulled towards one edge of their plateau
+        let peaks = samples
+            .chunks(resolution)
+            .map(|ys| {
+                let max = ys.iter().copied().fold(f32::MIN, f32::max);
+                let (sum, n) = ys
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &y)| max - y <= f32::EPSILON)
+                    .fold((0, 0), |(sum, n), (i, _)| (sum + i, n + 1));
+                sum as f32 / n as f32 / (resolution - 1) as f32
+            })
             .collect();
-        titles.sort_unstable_by_key(|(s, _)| {
-            let order = "NZLPSM-0+";
-            s.chars()
-                .map(|c| order.find(c).unwrap_or(0) as u8)
-                .collect::<Vec<_>>()
-        });
         Fuzzy {
+            name: input_type.to_string(),
+            unit: String::new(),
             members: functions.len(),
-            graph: Graph::new(input_type.to_string(), titles, None, Some(range.clone())),
+            terms,
+            index,
+            duplicates,
+            defuzzifier: Defuzzifier::Centroid,
+            out_of_range: OutOfRange::Clamp,
+            peaks,
+            samples,
             range,
             functions,
-            last_input: 0.,
-            resolution: 100,
-            last_output: vec![(0., 0.); 101],
+            resolution,
         }
     }
-    pub fn fuzzify(&mut self, x: f32) -> Vec<(V, f32)> {
-        self.last_input = (x - self.range.start) / (self.range.end - self.range.start);
-        let mut result = Vec::with_capacity(self.members);
-        for (&l, f) in self.functions.iter() {
-            result.push((l, f(self.last_input)));
+    /// Sets the unit shown on the axis of the graph, e.g. `m` or `rad`.
+    pub fn with_unit(mut self, unit: impl Display) -> Self {
+        self.unit = unit.to_string();
+        self
+    }
+
+    pub fn with_out_of_range(mut self, policy: OutOfRange) -> Self {
+        self.out_of_range = policy;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Terms in order from lowest to highest.
+    pub fn terms(&self) -> &[V] {
+        &self.terms
+    }
+
+    /// Terms that were given more than one membership function.
+    pub fn duplicates(&self) -> &[V] {
+        &self.duplicates
+    }
+
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    /// Position of `term` in [`terms`](Self::terms).
+    pub fn index(&self, term: &V) -> Option<usize> {
+        self.index.get(term).copied()
+    }
+
+    pub fn contains(&self, term: &V) -> bool {
+        self.index.contains_key(term)
+    }
+
+    /// Membership function of `term` in the units of the range.
+    pub fn function(&self, term: &V) -> Option<Mf> {
+        Some(self.functions[self.index(term)?].in_units(&self.range))
+    }
+
+    /// Membership functions of the terms in order, normalized over the range.
+    pub(crate) fn functions(&self) -> &[Mf] {
+        &self.functions
+    }
+
+    pub fn range(&self) -> &Range<f32> {
+        &self.range
+    }
+
+    /// Checks that the universe is a non-empty range.
+    pub fn validate(&self) -> Result<(), FuzzyError> {
+        if self.range.is_empty() || !self.range.end.is_finite() || !self.range.start.is_finite() {
+            return Err(FuzzyError::InvalidRange(
+                self.name.clone(),
+                self.range.clone(),
+            ));
         }
-        result
+        Ok(())
     }
 
-    pub fn defuzzify(&mut self, acuts: HashMap<V, f32>) -> f32 {
-        if acuts.len() != self.members {
-            panic!(
-                "Length of alpha cuts ({}) != Length of membership functions ({})",
-                acuts.len(),
-                self.members
-            );
+    /// Membership of `x` in every term, handling values outside the range
+    /// according to [`out_of_range`](Self::out_of_range).
+    pub fn fuzzify(&self, x: f32) -> Result<Vec<(V, f32)>, FuzzyError> {
+        let mut memberships = vec![0.; self.members];
+        self.memberships(x, &mut memberships)?;
+        Ok(self.terms.iter().cloned().zip(memberships).collect())
+    }
+
+    /// Same as [`fuzzify`](Self::fuzzify), writing the membership of each term
+    /// to `out` in the order of [`terms`](Self::terms).
+    pub fn memberships(&self, x: f32, out: &mut [f32]) -> Result<(), FuzzyError> {
+        let t = (x - self.range.start) / (self.range.end - self.range.start);
+        let out = &mut out[..self.members];
+        if (0. ..=1.).contains(&t) {
+            for (mu, f) in out.iter_mut().zip(&self.functions) {
+                *mu = f.eval(t);
+            }
+            return Ok(());
+        }
+        match self.out_of_range {
+            OutOfRange::Clamp | OutOfRange::Extrapolate => {
+                let t = match self.out_of_range {
+                    OutOfRange::Clamp => t.clamp(0., 1.),
+                    _ => t,
+                };
+                for (mu, f) in out.iter_mut().zip(&self.functions) {
+                    *mu = f.eval(t);
+                }
+            }
+            OutOfRange::Saturate => {
+                let peaks = self.peaks.iter().enumerate();
+                let edge = if t < 0. {
+                    peaks.min_by(|a, b| a.1.total_cmp(b.1))
+                } else {
+                    peaks.max_by(|a, b| a.1.total_cmp(b.1))
+                }
+                .map(|(i, _)| i);
+                for (i, mu) in out.iter_mut().enumerate() {
+                    *mu = if Some(i) == edge { 1. } else { 0. };
+                }
+            }
+            OutOfRange::Flag => {
+                return Err(FuzzyError::OutOfRange(
+                    self.name.clone(),
+                    x,
+                    self.range.clone(),
+                ))
+            }
         }
-        let (mut mx, mut my, mut m) = (0., 0., 0.);
-        for i in 0..self.resolution {
+        Ok(())
+    }
+
+    pub fn defuzzify(
+        &self,
+        acuts: HashMap<V, f32>,
+        implication: Implication,
+        aggregation: Aggregation,
+    ) -> f32 {
+        // Terms missing from the alpha cuts have not fired at all
+        let acuts: Vec<f32> = self
+            .terms
+            .iter()
+            .map(|l| acuts.get(l).copied().unwrap_or(0.))
+            .collect();
+        let mut curve = vec![(0., 0.); self.resolution];
+        self.aggregate(&acuts, implication, aggregation, &mut curve);
+        self.to_units(self.point(&curve, &acuts).0)
+    }
+
+    /// Samples the aggregated output set over the normalized range into `curve`,
+    /// which holds [`resolution`](Self::resolution) points.
+    pub(crate) fn aggregate(
+        &self,
+        acuts: &[f32],
+        implication: Implication,
+        aggregation: Aggregation,
+        curve: &mut [(f32, f32)],
+    ) {
+        for (i, point) in curve.iter_mut().enumerate() {
             let x = i as f32 / (self.resolution - 1) as f32;
-            let y = acuts
+            let y = self
+                .samples
                 .iter()
-                .fold(0f32, |acc, (l, &a)| acc.max(self.functions[l](x).min(a)));
-            self.last_output[i + 1] = (x, y);
-            mx += y * x;
-            my += y * y;
-            m += y;
-            // println!("{} {} {}", mx, my, m);
+                .skip(i)
+                .step_by(self.resolution)
+                .zip(acuts)
+                .fold(0f32, |acc, (&mu, &a)| {
+                    aggregation.apply(acc, implication.apply(mu, a))
+                });
+            *point = (x, y);
         }
-        mx /= m;
-        my /= 2. * m;
-        self.last_output[0] = (mx, my);
-        mx * (self.range.end - self.range.start) + self.range.start
     }
 
-    pub fn draw(
-        &self,
-        ctx: &Context,
-        pos: (f32, f32),
-        size: (f32, f32),
-        is_output: bool,
-    ) -> Vec<f32> {
-        self.graph.draw(
-            ctx,
-            pos,
-            size,
-            if is_output {
-                None
-            } else {
-                Some(self.last_input)
-            },
-            if is_output {
-                Some(&self.last_output)
-            } else {
-                None
-            },
-        )
+    /// Number of points the output set is sampled at.
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    /// Normalized point the defuzzifier picks on an aggregated `curve`.
+    pub(crate) fn point(&self, curve: &[(f32, f32)], acuts: &[f32]) -> (f32, f32) {
+        let at = |x: f32| curve[(x * (self.resolution - 1) as f32).round() as usize].1;
+        match self.defuzzifier {
+            Defuzzifier::Centroid => {
+                let (mut mx, mut my, mut m) = (0., 0., 0.);
+                for &(x, y) in curve {
+                    mx += y * x;
+                    my += y * y;
+                    m += y;
+                }
+                (mx / m, my / (2. * m))
+            }
+            Defuzzifier::Bisector => {
+                let half = curve.iter().map(|(_, y)| y).sum::<f32>() / 2.;
+                let mut area = 0.;
+                let x = curve
+                    .iter()
+                    .find(|(_, y)| {
+                        area += y;
+                        area >= half
+                    })
+                    .map_or(f32::NAN, |(x, _)| *x);
+                (x, at(x))
+            }
+            Defuzzifier::MeanOfMax | Defuzzifier::SmallestOfMax | Defuzzifier::LargestOfMax => {
+                let max = curve.iter().fold(0f32, |acc, (_, y)| acc.max(*y));
+                let maxima = curve
+                    .iter()
+                    .filter(|(_, y)| max > 0. && max - y <= f32::EPSILON)
+                    .map(|(x, _)| *x);
+                let x = match self.defuzzifier {
+                    Defuzzifier::SmallestOfMax => maxima.reduce(f32::min),
+                    Defuzzifier::LargestOfMax => maxima.reduce(f32::max),
+                    _ => {
+                        let (sum, n) = maxima.fold((0., 0.), |(s, n), x| (s + x, n + 1.));
+                        Some(sum / n)
+                    }
+                }
+                .unwrap_or(f32::NAN);
+                (x, max)
+            }
+            Defuzzifier::WeightedAverage => {
+                let (mut mx, mut m) = (0., 0.);
+                for (&a, &peak) in acuts.iter().zip(&self.peaks) {
+                    mx += a * peak;
+                    m += a;
+                }
+                let x = mx / m;
+                (x, at(x))
+            }
+        }
+    }
+
+    pub(crate) fn to_units(&self, x: f32) -> f32 {
+        x * (self.range.end - self.range.start) + self.range.start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        funcs::{cliff, mount, tri},
+        rules::Diff,
+    };
+
+    /// Two terms cut at one half: a plateau over 1..3 and a wider one over 5.5..8.5.
+    fn output() -> (Fuzzy<Diff>, HashMap<Diff, f32>) {
+        let fuzzy = Fuzzy::new(
+            "Out",
+            HashMap::from([(Diff::NS, tri(0., 2., 4.)), (Diff::PS, tri(4., 7., 10.))]),
+            0.0..10.,
+        );
+        let acuts = HashMap::from([(Diff::NS, 0.5), (Diff::PS, 0.5)]);
+        (fuzzy, acuts)
+    }
+
+    #[test]
+    fn defuzzifiers_pick_their_points() {
+        let (mut fuzzy, acuts) = output();
+        // The sets are sampled every 10/99, so the points are only that close
+        for (method, expected) in [
+            (Defuzzifier::Centroid, 5.),
+            (Defuzzifier::Bisector, 5.5),
+            (Defuzzifier::MeanOfMax, 5.),
+            (Defuzzifier::SmallestOfMax, 1.),
+            (Defuzzifier::LargestOfMax, 8.5),
+            (Defuzzifier::WeightedAverage, 4.5),
+        ] {
+            fuzzy.defuzzifier = method;
+            let x = fuzzy.defuzzify(acuts.clone(), Implication::Min, Aggregation::Max);
+            assert!((x - expected).abs() < 0.11, "{} gave {}", method, x);
+        }
+    }
+
+    #[test]
+    fn weighted_averages_take_the_middle_of_plateaus() {
+        let mut fuzzy = Fuzzy::new(
+            "Out",
+            HashMap::from([(Diff::NL, cliff(2., 4.)), (Diff::PL, mount(6., 8.))]),
+            0.0..10.,
+        );
+        fuzzy.defuzzifier = Defuzzifier::WeightedAverage;
+        // The shoulders are flat over 0..2 and 8..10
+        for (acuts, expected) in [([1., 1.], 5.), ([1., 0.], 1.), ([0., 1.], 9.)] {
+            let acuts = HashMap::from([(Diff::NL, acuts[0]), (Diff::PL, acuts[1])]);
+            let x = fuzzy.defuzzify(acuts, Implication::Min, Aggregation::Max);
+            assert!((x - expected).abs() < 0.06, "{} instead of {}", x, expected);
+        }
+    }
+
+    #[test]
+    fn implications_shape_the_sets() {
+        let (mut fuzzy, acuts) = output();
+        fuzzy.defuzzifier = Defuzzifier::MeanOfMax;
+        // Scaled rather than clipped, each set is highest only at its peak
+        let x = fuzzy.defuzzify(acuts, Implication::Product, Aggregation::Max);
+        assert!((x - 4.5).abs() < 0.11, "{}", x);
+        assert_eq!(Implication::Min.apply(0.8, 0.5), 0.5);
+        assert_eq!(Implication::Product.apply(0.8, 0.5), 0.4);
+    }
+
+    #[test]
+    fn aggregations_combine_overlapping_sets() {
+        assert_eq!(Aggregation::Max.apply(0.6, 0.7), 0.7);
+        assert_eq!(Aggregation::BoundedSum.apply(0.6, 0.7), 1.);
+        assert!((Aggregation::ProbOr.apply(0.6, 0.7) - 0.88).abs() < 1e-6);
+        let fuzzy: Fuzzy<Diff> = Fuzzy::new(
+            "Out",
+            HashMap::from([(Diff::NS, tri(0., 4., 8.)), (Diff::PS, tri(2., 6., 10.))]),
+            0.0..10.,
+        );
+        // Near 5, where the sets cross at three quarters each
+        let at_crossing = |aggregation: Aggregation| {
+            let mut curve = vec![(0., 0.); fuzzy.resolution()];
+            fuzzy.aggregate(&[1., 1.], Implication::Min, aggregation, &mut curve);
+            curve[49].1
+        };
+        assert!((at_crossing(Aggregation::Max) - 0.75).abs() < 0.02);
+        assert_eq!(at_crossing(Aggregation::BoundedSum), 1.);
+        assert!((at_crossing(Aggregation::ProbOr) - 0.9375).abs() < 0.01);
+    }
+
+    #[test]
+    fn out_of_range_policies_handle_outside_values() {
+        // Ramps that keep going past both ends of the range
+        let input = |policy| {
+            Fuzzy::<String>::new(
+                "X",
+                [
+                    ("L".to_string(), tri(-5., 0., 5.)),
+                    ("H".to_string(), tri(5., 10., 15.)),
+                ],
+                0.0..10.,
+            )
+            .with_out_of_range(policy)
+        };
+        let memberships = |policy, x| -> Result<Vec<f32>, FuzzyError> {
+            let mu: HashMap<String, f32> = input(policy).fuzzify(x)?.into_iter().collect();
+            Ok(vec![mu["L"], mu["H"]])
+        };
+        for (policy, below, above) in [
+            (OutOfRange::Clamp, [1., 0.], [0., 1.]),
+            (OutOfRange::Saturate, [1., 0.], [0., 1.]),
+            (OutOfRange::Extrapolate, [0.6, 0.], [0., 0.6]),
+        ] {
+            let close = |x, expected: [f32; 2]| {
+                let mu = memberships(policy, x).unwrap();
+                assert!(
+                    mu.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-5),
+                    "{:?} gave {:?} at {}",
+                    policy,
+                    mu,
+                    x
+                );
+            };
+            close(-2., below);
+            close(12., above);
+            close(3., [0.4, 0.]);
+        }
+        assert_eq!(
+            memberships(OutOfRange::Flag, 12.),
+            Err(FuzzyError::OutOfRange("X".to_string(), 12., 0.0..10.))
+        );
+        assert!((memberships(OutOfRange::Flag, 3.).unwrap()[0] - 0.4).abs() < 1e-5);
     }
 }
@@ -0,0 +1,17 @@
+pub mod bezier;
+pub mod drone;
+pub mod error;
+pub mod funcs;
+pub mod fuzzy;
+pub mod lut;
+pub mod mamdani;
+pub mod parser;
+pub mod partition;
+pub mod program;
+pub mod rules;
+pub mod snapshot;
+pub mod state;
+pub mod sugeno;
+pub mod trace;
+pub mod ui;
+pub mod variables;
@@ -0,0 +1,336 @@
+use std::{fmt::Display, ops::Range};
+
+use crate::{error::FuzzyError, mamdani::Mamdani, snapshot::Snapshot, variables::Var};
+
+/// A controller baked into a grid over its input universes, read back with
+/// multilinear interpolation.
+///
+/// Inputs outside a universe are clamped to it. Grid points where no rule
+/// fires hold whatever the controller's [`NoFiring`](crate::mamdani::NoFiring)
+/// policy gave while baking.
+#[derive(Debug, Clone)]
+pub struct Lut {
+    axes: Vec<Axis>,
+    /// Output at every grid point, the last axis varying fastest.
+    values: Vec<f32>,
+}
+
+/// Evenly spaced grid points over the universe of one input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis {
+    pub var: Var,
+    pub range: Range<f32>,
+    /// At least two, the ends of the range included.
+    pub points: usize,
+}
+
+impl Axis {
+    fn new(var: &Var, range: &Range<f32>, points: usize) -> Result<Axis, FuzzyError> {
+        if points < 2 {
+            return Err(FuzzyError::TooFewPoints(var.clone(), points));
+        }
+        Ok(Axis {
+            var: var.clone(),
+            range: range.clone(),
+            points,
+        })
+    }
+
+    fn at(&self, i: usize) -> f32 {
+        // The last point is exactly the end, not just close to it
+        if i + 1 == self.points {
+            return self.range.end;
+        }
+        let step = (self.range.end - self.range.start) / (self.points - 1) as f32;
+        self.range.start + i as f32 * step
+    }
+
+    /// Cell holding `x` and how far along it `x` lies, clamped to the range.
+    fn locate(&self, x: f32) -> (usize, f32) {
+        let t = ((x - self.range.start) / (self.range.end - self.range.start)).clamp(0., 1.)
+            * (self.points - 1) as f32;
+        let i = (t as usize).min(self.points - 2);
+        (i, t - i as f32)
+    }
+}
+
+/// How far a [`Lut`] strays from the controller it was baked from.
+#[derive(Debug, Clone)]
+pub struct LutReport {
+    pub samples: usize,
+    /// Largest absolute error, and where it happened.
+    pub max: f32,
+    pub worst: Vec<(Var, f32)>,
+    /// Mean absolute error.
+    pub mean: f32,
+    /// Root mean square error.
+    pub rms: f32,
+}
+
+impl Display for LutReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let worst: Vec<String> = self
+            .worst
+            .iter()
+            .map(|(var, x)| format!("{} = {:.3}", var, x))
+            .collect();
+        write!(
+            f,
+            "max {:.4} at {}, mean {:.4}, rms {:.4} over {} samples",
+            self.max,
+            worst.join(", "),
+            self.mean,
+            self.rms,
+            self.samples
+        )
+    }
+}
+
+/// Calls `f` with the inputs at every point of the grid, the last axis varying fastest.
+fn each_point(
+    axes: &[Axis],
+    mut f: impl FnMut(&[(Var, f32)]) -> Result<(), FuzzyError>,
+) -> Result<(), FuzzyError> {
+    let total: usize = axes.iter().map(|a| a.points).product();
+    let mut inputs: Vec<(Var, f32)> = axes.iter().map(|a| (a.var.clone(), 0.)).collect();
+    for k in 0..total {
+        let mut rest = k;
+        for (input, axis) in inputs.iter_mut().zip(axes).rev() {
+            input.1 = axis.at(rest % axis.points);
+            rest /= axis.points;
+        }
+        f(&inputs)?;
+    }
+    Ok(())
+}
+
+impl Lut {
+    /// Bakes `controller` with `points` grid points on the universe of every input.
+    pub fn bake(controller: &Mamdani, points: usize) -> Result<Lut, FuzzyError> {
+        let mut vars: Vec<Var> = controller.inputs().keys().cloned().collect();
+        vars.sort_by(|a, b| a.name().cmp(b.name()));
+        let axes: Vec<(Var, usize)> = vars.into_iter().map(|v| (v, points)).collect();
+        Lut::bake_axes(controller, &axes)
+    }
+
+    /// Bakes `controller` with a grid of the given number of points per input,
+    /// the inputs in the order given, each once and with at least two points.
+    ///
+    /// Every point is inferred with a fresh [`Snapshot`], so that the table does
+    /// not depend on the order of the grid: where no rule fires,
+    /// [`NoFiring::Hold`](crate::mamdani::NoFiring::Hold) bakes the output it
+    /// holds before any inference, zero.
+    pub fn bake_axes(controller: &Mamdani, axes: &[(Var, usize)]) -> Result<Lut, FuzzyError> {
+        let axes = axes
+            .iter()
+            .enumerate()
+            .map(|(d, (var, points))| {
+                let fuzzy = controller
+                    .inputs()
+                    .get(var)
+                    .ok_or_else(|| FuzzyError::UnknownInput(var.clone()))?;
+                if axes[..d].iter().any(|(v, _)| v == var) {
+                    return Err(FuzzyError::DuplicateVariable(var.clone()));
+                }
+                Axis::new(var, fuzzy.range(), *points)
+            })
+            .collect::<Result<Vec<_>, FuzzyError>>()?;
+        let mut values = Vec::with_capacity(axes.iter().map(|a| a.points).product());
+        each_point(&axes, |inputs| {
+            values.push(controller.infer(inputs, &mut Snapshot::new())?);
+            Ok(())
+        })?;
+        Ok(Lut { axes, values })
+    }
+
+    pub fn axes(&self) -> &[Axis] {
+        &self.axes
+    }
+
+    /// Output at every grid point, the last axis varying fastest.
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Interpolated output for the crisp inputs. Does not allocate unless it fails.
+    pub fn eval(&self, inputs: &[(Var, f32)]) -> Result<f32, FuzzyError> {
+        let mut value = 0.;
+        for corner in 0..1usize << self.axes.len() {
+            let (mut weight, mut offset) = (1., 0);
+            for (d, axis) in self.axes.iter().enumerate() {
+                let (_, x) = inputs
+                    .iter()
+                    .find(|(v, _)| v == &axis.var)
+                    .ok_or_else(|| FuzzyError::MissingInput(axis.var.clone()))?;
+                let (i, t) = axis.locate(*x);
+                let up = (corner >> (self.axes.len() - 1 - d)) & 1;
+                weight *= if up == 1 { t } else { 1. - t };
+                offset = offset * axis.points + i + up;
+            }
+            if weight > 0. {
+                value += weight * self.values[offset];
+            }
+        }
+        Ok(value)
+    }
+
+    /// Compares the table with `controller` on a grid of `points` samples per
+    /// input, at least two, each inferred with a fresh [`Snapshot`] as when
+    /// baking. An odd multiple of the baked grid also lands between its points,
+    /// where the error is largest.
+    pub fn report(&self, controller: &Mamdani, points: usize) -> Result<LutReport, FuzzyError> {
+        let samples = self
+            .axes
+            .iter()
+            .map(|a| Axis::new(&a.var, &a.range, points))
+            .collect::<Result<Vec<_>, FuzzyError>>()?;
+        let mut report = LutReport {
+            samples: 0,
+            max: 0.,
+            worst: Vec::new(),
+            mean: 0.,
+            rms: 0.,
+        };
+        each_point(&samples, |inputs| {
+            let exact = controller.infer(inputs, &mut Snapshot::new())?;
+            let error = (self.eval(inputs)? - exact).abs();
+            report.samples += 1;
+            report.mean += error;
+            report.rms += error * error;
+            if error > report.max || report.worst.is_empty() {
+                report.max = error;
+                report.worst = inputs.to_vec();
+            }
+            Ok(())
+        })?;
+        let n = report.samples as f32;
+        report.mean /= n;
+        report.rms = (report.rms / n).sqrt();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        funcs::{smf, tri, zmf},
+        fuzzy::Fuzzy,
+        mamdani::{MamdaniBuilder, NoFiring},
+    };
+
+    fn builder(rules: &str) -> MamdaniBuilder {
+        let (temp, hum, fan) = (Var::new("Temp"), Var::new("Hum"), Var::new("Fan"));
+        Mamdani::builder()
+            .input(
+                temp.clone(),
+                Fuzzy::new(
+                    &temp,
+                    [
+                        (temp.term("Cold"), zmf(0., 40.)),
+                        (temp.term("Hot"), smf(0., 40.)),
+                    ],
+                    0.0..40.,
+                ),
+            )
+            .input(
+                hum.clone(),
+                Fuzzy::new(
+                    &hum,
+                    [
+                        (hum.term("Dry"), zmf(0., 100.)),
+                        (hum.term("Wet"), smf(0., 100.)),
+                    ],
+                    0.0..100.,
+                ),
+            )
+            .output(Fuzzy::new(
+                &fan,
+                [
+                    (fan.term("Slow"), tri(0., 20., 50.)),
+                    (fan.term("Fast"), tri(50., 80., 100.)),
+                ],
+                0.0..100.,
+            ))
+            .text_rules(rules)
+    }
+
+    fn controller() -> Mamdani {
+        builder(
+            "IF Temp IS Cold OR Hum IS Dry THEN Fan IS Slow
+             IF Temp IS Hot AND Hum IS Wet THEN Fan IS Fast",
+        )
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn matches_the_controller_on_the_grid() {
+        let m = controller();
+        let lut = Lut::bake(&m, 5).unwrap();
+        assert_eq!(lut.values().len(), 25);
+        for (temp, hum) in [(0., 0.), (10., 75.), (40., 100.), (30., 25.)] {
+            let inputs = [(Var::new("Temp"), temp), (Var::new("Hum"), hum)];
+            let exact = m.infer(&inputs, &mut Snapshot::new()).unwrap();
+            assert!((lut.eval(&inputs).unwrap() - exact).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn finer_grids_are_closer() {
+        let m = controller();
+        let coarse = Lut::bake(&m, 3).unwrap().report(&m, 21).unwrap();
+        let fine = Lut::bake(&m, 11).unwrap().report(&m, 21).unwrap();
+        assert_eq!(fine.samples, 441);
+        assert!(fine.max < coarse.max, "{} vs {}", fine, coarse);
+        assert!(fine.rms < coarse.rms);
+    }
+
+    #[test]
+    fn gaps_do_not_depend_on_the_grid_order() {
+        // Nothing fires where it is hot and dry or cold and wet
+        let m = builder(
+            "IF Temp IS Cold AND Hum IS Dry THEN Fan IS Slow
+             IF Temp IS Hot AND Hum IS Wet THEN Fan IS Fast",
+        )
+        .no_firing(NoFiring::Hold)
+        .build()
+        .unwrap();
+        let (temp, hum) = (Var::new("Temp"), Var::new("Hum"));
+        let by_temp = Lut::bake_axes(&m, &[(temp.clone(), 5), (hum.clone(), 5)]).unwrap();
+        let by_hum = Lut::bake_axes(&m, &[(hum.clone(), 5), (temp.clone(), 5)]).unwrap();
+        for (i, j) in (0..5).flat_map(|i| (0..5).map(move |j| (i, j))) {
+            assert_eq!(by_temp.values()[i * 5 + j], by_hum.values()[j * 5 + i]);
+        }
+        assert_eq!(by_temp.values()[20], 0.);
+        assert_eq!(by_temp.values()[4], 0.);
+        let hot_and_dry = [(temp, 40.), (hum, 0.)];
+        assert_eq!(by_hum.eval(&hot_and_dry), Ok(0.));
+        assert_eq!(by_temp.report(&m, 5).unwrap().max, 0.);
+    }
+
+    #[test]
+    fn grids_need_two_points_on_each_axis() {
+        let m = controller();
+        let (temp, hum) = (Var::new("Temp"), Var::new("Hum"));
+        assert_eq!(
+            Lut::bake_axes(&m, &[(temp.clone(), 5), (hum.clone(), 1)]).err(),
+            Some(FuzzyError::TooFewPoints(hum, 1))
+        );
+        let lut = Lut::bake(&m, 5).unwrap();
+        assert_eq!(
+            lut.report(&m, 0).err(),
+            Some(FuzzyError::TooFewPoints(lut.axes()[0].var.clone(), 0))
+        );
+    }
+
+    #[test]
+    fn grids_take_each_input_once() {
+        let m = controller();
+        let temp = Var::new("Temp");
+        assert_eq!(
+            Lut::bake_axes(&m, &[(temp.clone(), 5), (temp.clone(), 3)]).err(),
+            Some(FuzzyError::DuplicateVariable(temp))
+        );
+    }
+}
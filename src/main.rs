@@ -1,27 +1,20 @@
-mod bezier;
-mod drone;
-mod funcs;
-mod fuzzy;
-mod mamdani;
-mod rules;
-mod state;
-mod ui;
-use drone::Drone;
 use egui_macroquad::egui::{self};
-use funcs::*;
-use fuzzy::Fuzzy;
+use fuzzy::drone::Drone;
+use fuzzy::funcs::*;
+use fuzzy::fuzzy::{Defuzzifier, Fuzzy};
+use fuzzy::mamdani::Mamdani;
+use fuzzy::partition::{Partition, Shape};
+use fuzzy::snapshot::Snapshot;
+use fuzzy::sugeno::{Consequent, Sugeno};
+use fuzzy::ui::{draw_blue_grid, smoke, Graph};
 use macroquad::prelude::*;
 use macroquad_particles::{Emitter, EmitterConfig};
-use mamdani::Mamdani;
 use std::collections::HashMap;
-use ui::{draw_blue_grid, smoke};
 
-use rules::{Amp, Diff, InputType, Inputs, Outputs, Th, Vx, Vy, W, X, Y};
+use fuzzy::rules::{Amp, Diff, InputType, Inputs, Outputs, Th, Vx, Vy, W, X, Y};
+use fuzzy::variables::{Term, Var};
 
-use crate::{
-    rules::Rule,
-    ui::{draw_rules, draw_vingette},
-};
+use fuzzy::ui::{draw_explanations, draw_rules, draw_vingette};
 
 fn window_conf() -> Conf {
     Conf {
@@ -55,142 +48,170 @@ async fn main() {
     let (thn, thz, thp) = (Inputs::Th(Th::N), Inputs::Th(Th::Z), Inputs::Th(Th::P));
     let (wn, wp) = (Inputs::W(W::N), Inputs::W(W::P));
     let (nl, nm, ns, z, ps, pm, pl) = (
-        Outputs::Diff(Diff::NL),
-        Outputs::Diff(Diff::NM),
-        Outputs::Diff(Diff::NS),
-        Outputs::Diff(Diff::Z),
-        Outputs::Diff(Diff::PS),
-        Outputs::Diff(Diff::PM),
-        Outputs::Diff(Diff::PL),
+        Term::from(Outputs::Diff(Diff::NL)),
+        Term::from(Outputs::Diff(Diff::NM)),
+        Term::from(Outputs::Diff(Diff::NS)),
+        Term::from(Outputs::Diff(Diff::Z)),
+        Term::from(Outputs::Diff(Diff::PS)),
+        Term::from(Outputs::Diff(Diff::PM)),
+        Term::from(Outputs::Diff(Diff::PL)),
     );
-    let mut m = Mamdani {
-        rules: vec![
-            (Outputs::Amp(Amp::Z), yp & vyp),
-            (Outputs::Amp(Amp::S), yp & vyn | yn & vyp),
-            (Outputs::Amp(Amp::L), yn & vyn),
-        ],
-        inputs: HashMap::from([
-            (
+    let mut m = Mamdani::builder()
+        .rule(Outputs::Amp(Amp::Z), yp & vyp)
+        .rule(Outputs::Amp(Amp::S), yp & vyn)
+        .rule(Outputs::Amp(Amp::S), yn & vyp)
+        .rule(Outputs::Amp(Amp::L), yn & vyn)
+        .input(
+            InputType::Y,
+            Fuzzy::new(
                 InputType::Y,
-                Fuzzy::new(
-                    InputType::Y,
-                    HashMap::from([(yn, zmf(0., 1.)), (yp, smf(0., 1.))]),
-                    -7.0..7.,
-                ),
-            ),
-            (
+                Partition::even(Shape::Smooth, &(-7.0..7.), 2)
+                    .unwrap()
+                    .terms(InputType::Y),
+                -7.0..7.,
+            )
+            .with_unit("m"),
+        )
+        .input(
+            InputType::Vy,
+            Fuzzy::new(
                 InputType::Vy,
-                Fuzzy::new(
-                    InputType::Vy,
-                    HashMap::from([(vyn, zmf(0.25, 0.75)), (vyp, smf(0.25, 0.75))]),
-                    -8.0..8.,
-                ),
-            ),
-        ]),
-        output: Fuzzy::new(
+                Partition::at(Shape::Smooth, [-4., 4.])
+                    .unwrap()
+                    .terms(InputType::Vy),
+                -8.0..8.,
+            )
+            .with_unit("m/s"),
+        )
+        .output(Fuzzy::new(
             "Amp",
-            HashMap::from([
-                (Outputs::Amp(Amp::Z), gbell(0.3, 3.5, 0.)),
-                (Outputs::Amp(Amp::S), gbell(0.2, 3., 0.5)),
-                (Outputs::Amp(Amp::L), gbell(0.2, 3., 1.)),
-            ]),
+            [
+                (Outputs::Amp(Amp::Z), gbell(3., 3.5, 0.)),
+                (Outputs::Amp(Amp::S), gbell(2., 3., 5.)),
+                (Outputs::Amp(Amp::L), gbell(2., 3., 10.)),
+            ],
             0.0..10.,
-        ),
-    };
+        ))
+        .build()
+        .unwrap();
 
-    let tp = vec![
-        vxp & thn & xn,
-        vxp & thn & xz,
-        vxp & thn & xp,
-        vxp & thz & xn,
-        vxp & thz & xz,
-        vxp & thz & xp,
-        vxp & thp & xn,
-        vxp & thp & xz,
-        vxp & thp & xp,
+    let diff_rules = vec![
+        (pm.clone(), vxp & thn & xn, 1.),
+        (pm.clone(), vxp & thn & xz, 1.),
+        (pl.clone(), vxp & thn & xp, 1.),
+        (ns.clone(), vxp & thz & xn, 1.),
+        (ps.clone(), vxp & thz & xz, 1.),
+        (pl.clone(), vxp & thz & xp, 1.),
+        (nm.clone(), vxp & thp & xn, 1.),
+        (nm.clone(), vxp & thp & xz, 1.),
+        (ns.clone(), vxp & thp & xp, 1.),
+        (ps.clone(), vxn & thn & xn, 1.),
+        (pm.clone(), vxn & thn & xz, 1.),
+        (pm.clone(), vxn & thn & xp, 1.),
+        (nl.clone(), vxn & thz & xn, 1.),
+        (ns.clone(), vxn & thz & xz, 1.),
+        (ps.clone(), vxn & thz & xp, 1.),
+        (nl.clone(), vxn & thp & xn, 1.),
+        (nm.clone(), vxn & thp & xz, 1.),
+        (nm.clone(), vxn & thp & xp, 1.),
+        (z.clone(), xz & thz, 1.),
+        (ns.clone(), wp.into(), 1.),
+        (ps.clone(), wn.into(), 1.),
     ];
-    let tn = vec![
-        vxn & thn & xn,
-        vxn & thn & xz,
-        vxn & thn & xp,
-        vxn & thz & xn,
-        vxn & thz & xz,
-        vxn & thz & xp,
-        vxn & thp & xn,
-        vxn & thp & xz,
-        vxn & thp & xp,
-    ];
-
-    fn r(v: &Vec<Rule>, i: usize) -> Rule {
-        v[i].clone()
-    }
-
-    let mut m2 = Mamdani {
-        rules: vec![
-            (nl, r(&tn, 6) | r(&tn, 3)),
-            (nm, r(&tn, 8) | r(&tp, 6) | r(&tp, 7) | r(&tn, 7)),
-            (ns, r(&tp, 3) | r(&tp, 8) | r(&tn, 4) | wp),
-            (z, xz & thz),
-            (ps, r(&tp, 4) | r(&tn, 5) | r(&tn, 0) | wn),
-            (pm, r(&tp, 0) | r(&tp, 1) | r(&tn, 2) | r(&tn, 1)),
-            (pl, r(&tp, 5) | r(&tp, 2)),
-        ],
-        inputs: HashMap::from([
-            (
+    let diff_inputs: HashMap<Var, Fuzzy<Term>> = HashMap::from([
+        (
+            InputType::X.into(),
+            Fuzzy::new(
                 InputType::X,
-                Fuzzy::new(
-                    InputType::X,
-                    HashMap::from([
-                        (xn, zmf(0., 0.9)),
-                        (xz, gbell(0.2, 1.5, 0.5)),
-                        (xp, smf(0.1, 1.)),
-                    ]),
-                    -10.0..10.,
-                ),
-            ),
-            (
+                [
+                    (xn, zmf(-10., 8.)),
+                    (xz, gbell(4., 1.5, 0.)),
+                    (xp, smf(-8., 10.)),
+                ],
+                -10.0..10.,
+            )
+            .with_unit("m"),
+        ),
+        (
+            InputType::Vx.into(),
+            Fuzzy::new(
                 InputType::Vx,
-                Fuzzy::new(
-                    InputType::Vx,
-                    HashMap::from([(vxn, zmf(0., 1.)), (vxp, smf(0., 1.))]),
-                    -4.0..4.,
-                ),
-            ),
-            (
+                Partition::even(Shape::Smooth, &(-4.0..4.), 2)
+                    .unwrap()
+                    .terms(InputType::Vx),
+                -4.0..4.,
+            )
+            .with_unit("m/s"),
+        ),
+        (
+            InputType::Th.into(),
+            Fuzzy::new(
                 InputType::Th,
-                Fuzzy::new(
-                    InputType::Th,
-                    HashMap::from([
-                        (thn, zmf(0., 0.9)),
-                        (thz, gbell(0.15, 2., 0.5)),
-                        (thp, smf(0.1, 1.)),
-                    ]),
-                    -0.5..0.5,
-                ),
-            ),
-            (
+                [
+                    (thn, zmf(-0.5, 0.4)),
+                    (thz, gbell(0.15, 2., 0.)),
+                    (thp, smf(-0.4, 0.5)),
+                ],
+                -0.5..0.5,
+            )
+            .with_unit("rad"),
+        ),
+        (
+            InputType::W.into(),
+            Fuzzy::new(
                 InputType::W,
-                Fuzzy::new(
-                    InputType::W,
-                    HashMap::from([(wn, zmf(0., 1.)), (wp, smf(0., 1.))]),
-                    -0.6..0.6,
-                ),
-            ),
-        ]),
-        output: Fuzzy::new(
+                Partition::even(Shape::Smooth, &(-0.6..0.6), 2)
+                    .unwrap()
+                    .terms(InputType::W),
+                -0.6..0.6,
+            )
+            .with_unit("rad/s"),
+        ),
+    ]);
+
+    let mut m2 = Mamdani::builder()
+        .rules(diff_rules.clone())
+        .inputs(diff_inputs.clone())
+        .output(Fuzzy::new(
             "Diff",
-            HashMap::from([
-                (Outputs::Diff(Diff::NL), gbell(0.1, 3., 0.)),
-                (Outputs::Diff(Diff::NM), gbell(0.1, 3., 0.3)),
-                (Outputs::Diff(Diff::NS), gbell(0.08, 3., 0.4)),
-                (Outputs::Diff(Diff::Z), gbell(0.02, 3., 0.5)),
-                (Outputs::Diff(Diff::PS), gbell(0.08, 3., 0.6)),
-                (Outputs::Diff(Diff::PM), gbell(0.1, 3., 0.7)),
-                (Outputs::Diff(Diff::PL), gbell(0.1, 3., 1.)),
-            ]),
+            [
+                (Outputs::Diff(Diff::NL), gbell(2., 3., -10.)),
+                (Outputs::Diff(Diff::NM), gbell(2., 3., -4.)),
+                (Outputs::Diff(Diff::NS), gbell(1.6, 3., -2.)),
+                (Outputs::Diff(Diff::Z), gbell(0.4, 3., 0.)),
+                (Outputs::Diff(Diff::PS), gbell(1.6, 3., 2.)),
+                (Outputs::Diff(Diff::PM), gbell(2., 3., 4.)),
+                (Outputs::Diff(Diff::PL), gbell(2., 3., 10.)),
+            ],
             -10.0..10.,
-        ),
-    };
+        ))
+        .build()
+        .unwrap();
+
+    // Same rules as m2, with each Diff term collapsed onto the center of its bell
+    let s2 = Sugeno::new(
+        diff_rules
+            .into_iter()
+            .map(|(out, rule, w)| {
+                let Some(Mf::GBell { c, .. }) = m2.output().function(&out) else {
+                    unreachable!("every Diff term is a bell");
+                };
+                (Consequent::Constant(c), rule, w)
+            })
+            .collect(),
+        diff_inputs,
+    )
+    .unwrap();
+    // Both diff controllers share their inputs, and so their graphs
+    let graphs: HashMap<Var, Graph> = m
+        .inputs()
+        .iter()
+        .chain(m2.inputs())
+        .map(|(var, fuzzy)| (var.clone(), Graph::of(fuzzy)))
+        .collect();
+    let (amp_graph, diff_graph) = (Graph::of(m.output()), Graph::of(m2.output()));
+    let mut use_sugeno = false;
+    let mut defuzzifier = 0;
 
     let mut drone = Drone::new(e1, e2);
     let vingette = Texture2D::from_file_with_format(include_bytes!("../vingette.png"), None);
@@ -200,63 +221,81 @@ async fn main() {
             break;
         }
 
+        if is_key_pressed(KeyCode::S) {
+            use_sugeno = !use_sugeno;
+            // The other controller holds, counts and plots nothing of this one
+            drone.snapshots.1 = Snapshot::new();
+        }
+        if is_key_pressed(KeyCode::D) {
+            defuzzifier = (defuzzifier + 1) % Defuzzifier::ALL.len();
+            m.output_mut().defuzzifier = Defuzzifier::ALL[defuzzifier];
+            m2.output_mut().defuzzifier = Defuzzifier::ALL[defuzzifier];
+        }
+
         clear_background(BLACK);
         draw_blue_grid(0.075, DARKGRAY, 0.001, 6, 0.002);
-        drone.update(&mut m, &mut m2, get_frame_time());
+        if use_sugeno {
+            drone.update(&m, &s2, get_frame_time());
+        } else {
+            drone.update(&m, &m2, get_frame_time());
+        }
         drone.display(WHITE, 0.05);
 
-        let mut fuzzied: HashMap<InputType, Vec<f32>> = HashMap::new();
-        let H = 200.;
-        let W = 250.;
+        let mut fuzzied: HashMap<Var, Vec<f32>> = HashMap::new();
+        let out_h = 200.;
+        let out_w = 250.;
         let gap = 10.;
         let title_gap = 0.;
-        let f = (2. * W - gap) / (3. * W);
-        let h = f * H;
-        let w = f * W;
+        let f = (2. * out_w - gap) / (3. * out_w);
+        let h = f * out_h;
+        let w = f * out_w;
         let top = 10.;
+        let (amp, diff) = &drone.snapshots;
+        let explanations: Vec<String> = [&drone.traces.0, &drone.traces.1]
+            .into_iter()
+            .flatten()
+            .map(|t| t.explain())
+            .collect();
         egui_macroquad::ui(|ctx: &egui::Context| {
-            fuzzied.insert(
-                InputType::Y,
-                m.inputs[&InputType::Y].draw(ctx, (gap, top), (w, h), false),
-            );
-            fuzzied.insert(
-                InputType::X,
-                m2.inputs[&InputType::X].draw(ctx, (w + 2. * gap, top), (w, h), false),
-            );
-            fuzzied.insert(
-                InputType::Th,
-                m2.inputs[&InputType::Th].draw(ctx, (2. * w + 3. * gap, top), (w, h), false),
-            );
-            fuzzied.insert(
-                InputType::Vy,
-                m.inputs[&InputType::Vy].draw(ctx, (gap, top + h + gap + title_gap), (w, h), false),
-            );
-            fuzzied.insert(
-                InputType::Vx,
-                m2.inputs[&InputType::Vx].draw(
-                    ctx,
+            let inputs = [
+                (InputType::Y, amp, (gap, top)),
+                (InputType::X, diff, (w + 2. * gap, top)),
+                (InputType::Th, diff, (2. * w + 3. * gap, top)),
+                (InputType::Vy, amp, (gap, top + h + gap + title_gap)),
+                (
+                    InputType::Vx,
+                    diff,
                     (w + 2. * gap, top + h + gap + title_gap),
-                    (w, h),
-                    false,
                 ),
-            );
-            fuzzied.insert(
-                InputType::W,
-                m2.inputs[&InputType::W].draw(
-                    ctx,
+                (
+                    InputType::W,
+                    diff,
                     (2. * w + 3. * gap, top + h + gap + title_gap),
-                    (w, h),
-                    false,
                 ),
+            ];
+            for (input, snapshot, pos) in inputs {
+                let var = Var::from(input);
+                let fuzzy = m.inputs().get(&var).or_else(|| m2.inputs().get(&var));
+                let fuzzy = fuzzy.expect("every graph is of an input");
+                let memberships =
+                    graphs[&var].draw_input(ctx, pos, (w, h), fuzzy, snapshot.input(&var));
+                fuzzied.insert(var, memberships);
+            }
+            amp_graph.draw_output(
+                ctx,
+                (gap, top + 2. * (h + gap + title_gap)),
+                (out_w, out_h),
+                amp.aggregate(),
+                m.output().defuzzifier,
             );
-            m.output
-                .draw(ctx, (gap, top + 2. * (h + gap + title_gap)), (W, H), true);
-            m2.output.draw(
+            diff_graph.draw_output(
                 ctx,
-                (W + 2. * gap, top + 2. * (h + gap + title_gap)),
-                (W, H),
-                true,
+                (out_w + 2. * gap, top + 2. * (h + gap + title_gap)),
+                (out_w, out_h),
+                diff.aggregate(),
+                m2.output().defuzzifier,
             );
+            draw_explanations(ctx, (560., 520.), 400., &explanations);
         });
         egui_macroquad::draw();
 
@@ -268,12 +307,19 @@ async fn main() {
         draw_rectangle_lines(gap, top + h + gap, w, h, 4., WHITE);
         draw_rectangle_lines(w + 2. * gap, top + h + gap, w, h, 4., WHITE);
         draw_rectangle_lines(2. * w + 3. * gap, top + h + gap, w, h, 4., WHITE);
-        draw_rectangle_lines(gap, top + 2. * (h + gap + title_gap), W, H, 4., WHITE);
         draw_rectangle_lines(
-            W + 2. * gap,
+            gap,
+            top + 2. * (h + gap + title_gap),
+            out_w,
+            out_h,
+            4.,
+            WHITE,
+        );
+        draw_rectangle_lines(
+            out_w + 2. * gap,
             top + 2. * (h + gap + title_gap),
-            W,
-            H,
+            out_w,
+            out_h,
             4.,
             WHITE,
         );
@@ -284,13 +330,14 @@ async fn main() {
             (
                 &[xn, xz, xp],
                 &[thn, thz, thp],
-                &[pm, pm, pl, ns, ps, pl, nm, nm, ns],
+                &[&pm, &pm, &pl, &ns, &ps, &pl, &nm, &nm, &ns],
                 &[vxp],
             ),
+            &m2.rules()[..9].iter().map(|r| r.2).collect::<Vec<_>>(),
             (
-                &fuzzied[&InputType::X],
-                &fuzzied[&InputType::Th],
-                Some(fuzzied[&InputType::Vx][1]),
+                &fuzzied[&Var::from(InputType::X)],
+                &fuzzied[&Var::from(InputType::Th)],
+                Some(fuzzied[&Var::from(InputType::Vx)][1]),
             ),
         );
         draw_rules(
@@ -300,13 +347,14 @@ async fn main() {
             (
                 &[xn, xz, xp],
                 &[thn, thz, thp],
-                &[ps, pm, pm, nl, ns, ps, nl, nm, nm],
+                &[&ps, &pm, &pm, &nl, &ns, &ps, &nl, &nm, &nm],
                 &[vxn],
             ),
+            &m2.rules()[9..18].iter().map(|r| r.2).collect::<Vec<_>>(),
             (
-                &fuzzied[&InputType::X],
-                &fuzzied[&InputType::Th],
-                Some(fuzzied[&InputType::Vx][0]),
+                &fuzzied[&Var::from(InputType::X)],
+                &fuzzied[&Var::from(InputType::Th)],
+                Some(fuzzied[&Var::from(InputType::Vx)][0]),
             ),
         );
         draw_rules(
@@ -324,15 +372,61 @@ async fn main() {
                 ],
                 &[""],
             ),
-            (&fuzzied[&InputType::Vy], &fuzzied[&InputType::Y], None),
+            &[
+                m.rules()[3].2,
+                m.rules()[1].2,
+                m.rules()[2].2,
+                m.rules()[0].2,
+            ],
+            (
+                &fuzzied[&Var::from(InputType::Vy)],
+                &fuzzied[&Var::from(InputType::Y)],
+                None,
+            ),
         );
 
         draw_rules(
             50.,
             (420., 630.),
             (2, 1),
-            (&[""], &[""], &[ns, ps], &[wp, wn]),
-            (&fuzzied[&InputType::W], &[1.], None),
+            (&[""], &[""], &[&ns, &ps], &[wp, wn]),
+            &[m2.rules()[19].2, m2.rules()[20].2],
+            (&fuzzied[&Var::from(InputType::W)], &[1.], None),
+        );
+
+        draw_text(
+            if use_sugeno {
+                "Diff: Sugeno [S]"
+            } else {
+                "Diff: Mamdani [S]"
+            },
+            40.,
+            712.,
+            16.,
+            WHITE,
+        );
+        draw_text(
+            &format!(
+                "No rule fired: Amp {}, Diff {}",
+                amp.misfires(),
+                diff.misfires()
+            ),
+            230.,
+            712.,
+            16.,
+            WHITE,
+        );
+        let outside = [amp, diff]
+            .into_iter()
+            .flat_map(|s| s.outside())
+            .map(|(var, n)| format!("{} {}", var, n))
+            .collect::<Vec<_>>();
+        draw_text(
+            &format!("Out of range: {}", outside.join(", ")),
+            450.,
+            712.,
+            16.,
+            WHITE,
         );
 
         draw_vingette(vingette);
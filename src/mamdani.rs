@@ -1,63 +1,1126 @@
 use std::collections::HashMap;
 
 use crate::{
+    error::FuzzyError,
+    funcs::Mf,
     fuzzy::Fuzzy,
-    rules::{InputType, Inputs, Op, Outputs, Rule, RuleNode},
+    parser::parse_rules,
+    program::{Program, Slots},
+    rules::{Norm, Rule},
+    snapshot::Snapshot,
+    trace::{InputTrace, RuleTrace, Trace},
+    variables::{Registry, Term, Var},
 };
 
+/// Shapes a consequent set by its rule's firing strength.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Implication {
+    /// Clip the set at the firing strength.
+    Min,
+    /// Scale the set by the firing strength.
+    Product,
+}
+
+impl Implication {
+    pub fn apply(&self, mu: f32, a: f32) -> f32 {
+        match self {
+            Implication::Min => mu.min(a),
+            Implication::Product => mu * a,
+        }
+    }
+}
+
+/// Combines the implied consequent sets into one output set.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Aggregation {
+    Max,
+    /// `min(1, a + b)`
+    BoundedSum,
+    /// `a + b - ab`
+    ProbOr,
+}
+
+impl Aggregation {
+    pub fn apply(&self, a: f32, b: f32) -> f32 {
+        match self {
+            Aggregation::Max => a.max(b),
+            Aggregation::BoundedSum => (a + b).min(1.),
+            Aggregation::ProbOr => a + b - a * b,
+        }
+    }
+}
+
+/// What a controller outputs when none of its rules fire.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum NoFiring {
+    /// Repeat the last output that came from the rules.
+    Hold,
+    Default(f32),
+    /// Return [`FuzzyError::NoRuleFired`].
+    Error,
+}
+
+impl NoFiring {
+    /// Output when no rule fired, `held` being the last one that came from the rules.
+    pub fn output(&self, held: f32) -> Result<f32, FuzzyError> {
+        match self {
+            NoFiring::Hold => Ok(held),
+            NoFiring::Default(x) => Ok(*x),
+            NoFiring::Error => Err(FuzzyError::NoRuleFired),
+        }
+    }
+}
+
+/// Rows of a batch evaluated side by side.
+const BATCH_LANES: usize = 64;
+
 pub struct Mamdani {
-    pub rules: Vec<(Outputs, Rule)>,
-    pub inputs: HashMap<InputType, Fuzzy<Inputs>>,
-    pub output: Fuzzy<Outputs>,
+    rules: Vec<(Term, Rule, f32)>,
+    /// The rules compiled with `norm`.
+    program: Program,
+    slots: Slots,
+    /// Index of the output term of each rule.
+    consequents: Vec<usize>,
+    inputs: HashMap<Var, Fuzzy<Term>>,
+    output: Fuzzy<Term>,
+    norm: Norm,
+    implication: Implication,
+    aggregation: Aggregation,
+    accumulation: Aggregation,
+    no_firing: NoFiring,
 }
 
-impl Mamdani {
-    pub fn fuzzify(&mut self, crisp: &[(InputType, f32)]) -> HashMap<Inputs, f32> {
-        // println!("{:?}", crisp);
-        crisp
+/// Checks the universe of a variable and that none of its terms was given twice.
+fn validate_terms(fuzzy: &Fuzzy<Term>) -> impl Iterator<Item = FuzzyError> + '_ {
+    let duplicates = fuzzy
+        .duplicates()
+        .iter()
+        .map(|t| FuzzyError::DuplicateTerm(t.var().clone(), t.clone()));
+    fuzzy.validate().err().into_iter().chain(duplicates)
+}
+
+/// Checks the input universes and terms, the rule trees and weights, and that
+/// every rule input term has a membership function, collecting every problem found.
+pub(crate) fn validate_rules<'a>(
+    rules: impl Iterator<Item = (&'a Rule, f32)>,
+    inputs: &HashMap<Var, Fuzzy<Term>>,
+) -> Vec<FuzzyError> {
+    let mut errors: Vec<FuzzyError> = inputs.values().flat_map(validate_terms).collect();
+    for (rule, w) in rules {
+        if !(0. ..=1.).contains(&w) {
+            errors.push(FuzzyError::InvalidWeight(w));
+        }
+        if let Err(e) = rule.validate() {
+            errors.push(e);
+            continue;
+        }
+        for i in rule.inputs() {
+            let found = inputs.get(i.var()).is_some_and(|f| f.contains(&i));
+            let missing = FuzzyError::MissingMembership(i);
+            if !found && !errors.contains(&missing) {
+                errors.push(missing);
+            }
+        }
+    }
+    errors
+}
+
+/// Registry of the input variables and the output variable, each with the
+/// terms that have membership functions in their order.
+fn registry(inputs: &HashMap<Var, Fuzzy<Term>>, output: Option<&Fuzzy<Term>>) -> Registry {
+    let mut registry = Registry::new();
+    let output = output.map(|o| (Var::new(o.name()), o));
+    for (var, fuzzy) in inputs.iter().map(|(v, f)| (v.clone(), f)).chain(output) {
+        let terms = fuzzy.terms().iter().map(|t| t.name().to_string());
+        // A variable given twice is already reported by the rules that use it
+        registry.define(var, terms).ok();
+    }
+    registry
+}
+
+/// Fuzzifies each crisp input with the memberships of its variable.
+pub(crate) fn fuzzify_all(
+    inputs: &HashMap<Var, Fuzzy<Term>>,
+    crisp: &[(Var, f32)],
+) -> Result<HashMap<Term, f32>, FuzzyError> {
+    let mut finputs = HashMap::new();
+    for (i, x) in crisp {
+        let fuzzy = inputs
+            .get(i)
+            .ok_or_else(|| FuzzyError::UnknownInput(i.clone()))?;
+        finputs.extend(fuzzy.fuzzify(*x)?);
+    }
+    Ok(finputs)
+}
+
+/// Collects the parts of a [`Mamdani`] controller and checks them together in [`build`](Self::build).
+///
+/// Defaults to Zadeh AND and OR, min implication, max aggregation and accumulation,
+/// and holding the last output when no rule fires.
+pub struct MamdaniBuilder {
+    rules: Vec<(Term, Rule, f32)>,
+    text_rules: Vec<String>,
+    inputs: HashMap<Var, Fuzzy<Term>>,
+    /// Input variables given more than once, the last one kept.
+    duplicates: Vec<Var>,
+    output: Option<Fuzzy<Term>>,
+    norm: Norm,
+    implication: Implication,
+    aggregation: Aggregation,
+    accumulation: Aggregation,
+    no_firing: NoFiring,
+}
+
+impl Default for MamdaniBuilder {
+    fn default() -> Self {
+        MamdaniBuilder {
+            rules: Vec::new(),
+            text_rules: Vec::new(),
+            inputs: HashMap::new(),
+            duplicates: Vec::new(),
+            output: None,
+            norm: Norm::Zadeh,
+            implication: Implication::Min,
+            aggregation: Aggregation::Max,
+            accumulation: Aggregation::Max,
+            no_firing: NoFiring::Hold,
+        }
+    }
+}
+
+impl MamdaniBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn input(mut self, var: impl Into<Var>, fuzzy: Fuzzy<Term>) -> Self {
+        let var = var.into();
+        if self.inputs.contains_key(&var) && !self.duplicates.contains(&var) {
+            self.duplicates.push(var.clone());
+        }
+        self.inputs.insert(var, fuzzy);
+        self
+    }
+
+    pub fn inputs(self, inputs: impl IntoIterator<Item = (Var, Fuzzy<Term>)>) -> Self {
+        inputs
+            .into_iter()
+            .fold(self, |builder, (var, fuzzy)| builder.input(var, fuzzy))
+    }
+
+    pub fn output(mut self, fuzzy: Fuzzy<Term>) -> Self {
+        self.output = Some(fuzzy);
+        self
+    }
+
+    pub fn rule(self, out: impl Into<Term>, rule: impl Into<Rule>) -> Self {
+        self.weighted_rule(out, rule, 1.)
+    }
+
+    pub fn weighted_rule(
+        mut self,
+        out: impl Into<Term>,
+        rule: impl Into<Rule>,
+        weight: f32,
+    ) -> Self {
+        self.rules.push((out.into(), rule.into(), weight));
+        self
+    }
+
+    pub fn rules(mut self, rules: impl IntoIterator<Item = (Term, Rule, f32)>) -> Self {
+        self.rules.extend(rules);
+        self
+    }
+
+    /// Adds rules written as text, one per line, see [`parse_rules`].
+    /// They are parsed by [`build`](Self::build) against the variables given
+    /// to the builder, which also reports any parse errors.
+    pub fn text_rules(mut self, text: &str) -> Self {
+        self.text_rules.push(text.to_string());
+        self
+    }
+
+    /// Norm for the ANDs and ORs of rules that do not choose their own, see [`Rule::using`].
+    pub fn norm(mut self, norm: Norm) -> Self {
+        self.norm = norm;
+        self
+    }
+
+    pub fn implication(mut self, implication: Implication) -> Self {
+        self.implication = implication;
+        self
+    }
+
+    pub fn aggregation(mut self, aggregation: Aggregation) -> Self {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// How the strengths of rules sharing a consequent are ORed together.
+    pub fn accumulation(mut self, accumulation: Aggregation) -> Self {
+        self.accumulation = accumulation;
+        self
+    }
+
+    pub fn no_firing(mut self, policy: NoFiring) -> Self {
+        self.no_firing = policy;
+        self
+    }
+
+    /// Checks the universes, rule trees and weights, that every rule term has a
+    /// membership function, that every output term is used by some rule and is
+    /// not a singleton, and that no input variable or term was given twice.
+    pub fn build(mut self) -> Result<Mamdani, Vec<FuzzyError>> {
+        let mut errors: Vec<FuzzyError> = self
+            .duplicates
             .iter()
-            .flat_map(|(i, x)| {
-                self.inputs
-                    .get_mut(i)
-                    .unwrap_or_else(|| panic!("{} not found in memberships", i))
-                    .fuzzify(*x)
-            })
-            .collect()
+            .map(|v| FuzzyError::DuplicateVariable(v.clone()))
+            .collect();
+        if !self.norm.is_valid() {
+            errors.push(FuzzyError::InvalidNorm(self.norm));
+        }
+        let registry = registry(&self.inputs, self.output.as_ref());
+        for text in &self.text_rules {
+            match parse_rules(text, &registry) {
+                Ok(rules) => self.rules.extend(rules),
+                Err(e) => errors.extend(e),
+            }
+        }
+        errors.extend(validate_rules(
+            self.rules.iter().map(|(_, r, w)| (r, *w)),
+            &self.inputs,
+        ));
+        match &self.output {
+            Some(output) => {
+                errors.extend(validate_terms(output));
+                for (out, _, _) in self.rules.iter() {
+                    let missing = FuzzyError::MissingOutput(out.clone());
+                    if !output.contains(out) && !errors.contains(&missing) {
+                        errors.push(missing);
+                    }
+                }
+                for (out, f) in output.terms().iter().zip(output.functions()) {
+                    if !self.rules.iter().any(|(o, _, _)| o == out) {
+                        errors.push(FuzzyError::UncoveredOutput(out.clone()));
+                    }
+                    if let Mf::Singleton { .. } = f {
+                        errors.push(FuzzyError::SingletonOutput(out.clone()));
+                    }
+                }
+            }
+            None => errors.push(FuzzyError::MissingOutputVariable),
+        }
+        let compiled = Slots::compile(
+            self.rules.iter().map(|(_, r, w)| (r, *w)),
+            &self.inputs,
+            self.norm,
+        );
+        match (self.output, compiled) {
+            (Some(output), Ok((program, slots))) if errors.is_empty() => Ok(Mamdani {
+                consequents: self
+                    .rules
+                    .iter()
+                    .map(|(out, _, _)| output.index(out).expect("checked above"))
+                    .collect(),
+                rules: self.rules,
+                program,
+                slots,
+                inputs: self.inputs,
+                output,
+                norm: self.norm,
+                implication: self.implication,
+                aggregation: self.aggregation,
+                accumulation: self.accumulation,
+                no_firing: self.no_firing,
+            }),
+            // Anything that fails to compile has been reported by the checks above
+            (_, Err(e)) if errors.is_empty() => Err(vec![e]),
+            _ => Err(errors),
+        }
+    }
+}
+
+impl Mamdani {
+    pub fn builder() -> MamdaniBuilder {
+        MamdaniBuilder::new()
+    }
+
+    pub fn rules(&self) -> &[(Term, Rule, f32)] {
+        &self.rules
+    }
+
+    pub fn inputs(&self) -> &HashMap<Var, Fuzzy<Term>> {
+        &self.inputs
+    }
+
+    pub fn output(&self) -> &Fuzzy<Term> {
+        &self.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut Fuzzy<Term> {
+        &mut self.output
+    }
+
+    /// The variables and terms of the controller, for parsing rules against it.
+    pub fn registry(&self) -> Registry {
+        registry(&self.inputs, Some(&self.output))
+    }
+
+    pub fn norm(&self) -> Norm {
+        self.norm
+    }
+
+    /// Changes the norm of the rules that do not choose their own, leaving the
+    /// controller as it was if the norm is invalid.
+    pub fn set_norm(&mut self, norm: Norm) -> Result<(), FuzzyError> {
+        if !norm.is_valid() {
+            return Err(FuzzyError::InvalidNorm(norm));
+        }
+        (self.program, self.slots) = Slots::compile(
+            self.rules.iter().map(|(_, r, w)| (r, *w)),
+            &self.inputs,
+            norm,
+        )?;
+        self.norm = norm;
+        Ok(())
+    }
+
+    pub fn fuzzify(&self, crisp: &[(Var, f32)]) -> Result<HashMap<Term, f32>, FuzzyError> {
+        fuzzify_all(&self.inputs, crisp)
+    }
+
+    /// Firing strength of every rule, in the same order as `rules`.
+    pub fn fire(&self, finputs: &HashMap<Term, f32>) -> Result<Vec<f32>, FuzzyError> {
+        self.program.fire(finputs)
+    }
+
+    /// The rules as compiled for [`fire`](Self::fire).
+    pub fn program(&self) -> &Program {
+        &self.program
     }
 
-    fn resolve(&self, rule: &Rule, finputs: &HashMap<Inputs, f32>) -> f32 {
-        match &rule.val {
-            RuleNode::Input(i) => finputs[&i],
-            RuleNode::Op(o) => {
-                let left = self.resolve(rule.left.as_ref().expect("Op at end of tree"), finputs);
-                if let Op::Not(f) = o {
-                    if rule.right.is_some() {
-                        panic!("Not op must have only one (left) child");
+    /// Crisp output for the crisp inputs, recording them and the output set in
+    /// `snapshot`, which also has the last output for [`NoFiring::Hold`] and
+    /// counts the misfires. Runs the compiled rules over the buffers of the
+    /// snapshot, so once they fit this controller it does not allocate unless it fails.
+    pub fn infer(&self, inputs: &[(Var, f32)], snapshot: &mut Snapshot) -> Result<f32, FuzzyError> {
+        snapshot.reserve(
+            self.program.slots(),
+            self.slots.vars(),
+            self.output.terms().len(),
+            self.output.resolution(),
+        );
+        self.slots
+            .fill(&self.inputs, inputs, &self.program, snapshot)?;
+        let Snapshot {
+            values,
+            acuts,
+            aggregate,
+            ..
+        } = &mut *snapshot;
+        acuts.fill(0.);
+        for (&out, a) in self.consequents.iter().zip(self.program.strengths(values)) {
+            acuts[out] = self.accumulation.apply(acuts[out], a);
+        }
+        if self.program.strengths(values).all(|a| a <= 0.) {
+            return snapshot.fallback(self.no_firing);
+        }
+        let (point, curve) = aggregate.split_first_mut().expect("reserved above");
+        self.output
+            .aggregate(acuts, self.implication, self.aggregation, curve);
+        *point = self.output.point(curve, acuts);
+        // A set narrower than the sampling step fires but samples to nothing
+        if !Self::settled(*point, curve) {
+            aggregate.clear();
+            return snapshot.fallback(self.no_firing);
+        }
+        let crisp = self.output.to_units(point.0);
+        snapshot.record(crisp)
+    }
+
+    /// Whether a defuzzified `point` of the aggregated `curve` is an output,
+    /// rather than the guess of a set with no area.
+    fn settled(point: (f32, f32), curve: &[(f32, f32)]) -> bool {
+        point.0.is_finite() && curve.iter().any(|&(_, y)| y > 0.)
+    }
+
+    /// Crisp outputs for many input vectors at once. `inputs` holds a column of
+    /// crisp values per variable and `out` gets one output per row.
+    ///
+    /// Rows are taken in lanes, each instruction of the compiled rules running
+    /// over a whole lane at a time. Rows where no rule fires get the
+    /// [`NoFiring`] value, holding the last output of `snapshot` from before the
+    /// batch, and are counted in it; the inputs and output sets are not recorded.
+    pub fn infer_batch(
+        &self,
+        inputs: &[(Var, &[f32])],
+        out: &mut [f32],
+        snapshot: &mut Snapshot,
+    ) -> Result<(), FuzzyError> {
+        self.infer_batch_parallel(inputs, out, 1, snapshot)
+    }
+
+    /// Same as [`infer_batch`](Self::infer_batch), splitting the rows between
+    /// up to `threads` threads.
+    pub fn infer_batch_parallel(
+        &self,
+        inputs: &[(Var, &[f32])],
+        out: &mut [f32],
+        threads: usize,
+        snapshot: &mut Snapshot,
+    ) -> Result<(), FuzzyError> {
+        let columns: Vec<(&Fuzzy<Term>, usize, &[f32])> = self
+            .slots
+            .columns(inputs, out.len())?
+            .into_iter()
+            .map(|(var, first, column)| (&self.inputs[var], first, column))
+            .collect();
+        let held = snapshot.held();
+        let misfires = if threads <= 1 || out.len() <= BATCH_LANES {
+            self.batch(&columns, 0, out, held)?
+        } else {
+            let rows = out.len().div_ceil(threads);
+            std::thread::scope(|s| {
+                let columns = &columns;
+                let workers: Vec<_> = out
+                    .chunks_mut(rows)
+                    .enumerate()
+                    .map(|(k, chunk)| s.spawn(move || self.batch(columns, k * rows, chunk, held)))
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|w| w.join().expect("batch worker panicked"))
+                    .sum::<Result<usize, FuzzyError>>()
+            })?
+        };
+        snapshot.count_misfires(misfires);
+        Ok(())
+    }
+
+    /// Runs the rows of `out`, starting at row `start` of the columns, and
+    /// returns how many of them no rule fired for.
+    fn batch(
+        &self,
+        columns: &[(&Fuzzy<Term>, usize, &[f32])],
+        start: usize,
+        out: &mut [f32],
+        held: f32,
+    ) -> Result<usize, FuzzyError> {
+        let mut misfires = 0;
+        let most_terms = columns.iter().map(|(f, _, _)| f.terms().len()).max();
+        let mut memberships = vec![0.; most_terms.unwrap_or(0)];
+        let mut slots = vec![0.; self.program.slots() * BATCH_LANES];
+        let mut acuts = vec![0.; self.output.terms().len()];
+        let mut curve = vec![(0., 0.); self.output.resolution()];
+        for (c, lane_out) in out.chunks_mut(BATCH_LANES).enumerate() {
+            let row = start + c * BATCH_LANES;
+            let n = lane_out.len();
+            for &(fuzzy, first, column) in columns {
+                for (r, &x) in column[row..row + n].iter().enumerate() {
+                    fuzzy.memberships(x, &mut memberships)?;
+                    for (j, &mu) in memberships[..fuzzy.terms().len()].iter().enumerate() {
+                        slots[(first + j) * BATCH_LANES + r] = mu;
                     }
-                    return f(left);
                 }
-                let right = self.resolve(rule.right.as_ref().expect("Op at end of tree"), finputs);
-                match o {
-                    Op::And(f) => f(left, right),
-                    Op::Or(f) => f(left, right),
-                    _ => unreachable!(),
+            }
+            self.program.eval_lanes(&mut slots, BATCH_LANES, n);
+            for (r, y) in lane_out.iter_mut().enumerate() {
+                let strengths = || self.program.lane_strengths(&slots, BATCH_LANES, r);
+                if strengths().all(|a| a <= 0.) {
+                    *y = self.no_firing.output(held)?;
+                    misfires += 1;
+                    continue;
                 }
+                acuts.fill(0.);
+                for (&o, a) in self.consequents.iter().zip(strengths()) {
+                    acuts[o] = self.accumulation.apply(acuts[o], a);
+                }
+                self.output
+                    .aggregate(&acuts, self.implication, self.aggregation, &mut curve);
+                let point = self.output.point(&curve, &acuts);
+                if !Self::settled(point, &curve) {
+                    *y = self.no_firing.output(held)?;
+                    misfires += 1;
+                    continue;
+                }
+                *y = self.output.to_units(point.0);
             }
         }
+        Ok(misfires)
+    }
+
+    /// Same as [`infer`](Self::infer), also returning how the output came about.
+    pub fn infer_explained(
+        &self,
+        inputs: &[(Var, f32)],
+        snapshot: &mut Snapshot,
+    ) -> Result<(f32, Trace), FuzzyError> {
+        let crisp = self.infer(inputs, snapshot)?;
+        let values = &snapshot.values;
+        let input_traces = inputs
+            .iter()
+            .map(|(input, crisp)| {
+                let (fuzzy, first) = self
+                    .inputs
+                    .get(input)
+                    .zip(self.slots.first(input))
+                    .ok_or_else(|| FuzzyError::UnknownInput(input.clone()))?;
+                Ok(InputTrace {
+                    input: input.clone(),
+                    crisp: *crisp,
+                    memberships: fuzzy
+                        .terms()
+                        .iter()
+                        .cloned()
+                        .zip(values[first..].iter().copied())
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<_>, FuzzyError>>()?;
+        let rules = self
+            .rules
+            .iter()
+            .zip(self.program.strengths(values))
+            .map(|((output, rule, weight), strength)| {
+                Ok(RuleTrace {
+                    output: output.clone(),
+                    weight: *weight,
+                    strength,
+                    antecedent: self.program.trace(rule, values)?,
+                })
+            })
+            .collect::<Result<Vec<_>, FuzzyError>>()?;
+        let fired = rules.iter().any(|r| r.strength > 0.) && !snapshot.aggregate().is_empty();
+        let mut trace = Trace {
+            variable: self.output.name().to_string(),
+            inputs: input_traces,
+            rules,
+            outputs: self
+                .output
+                .terms()
+                .iter()
+                .cloned()
+                .zip(snapshot.acuts.iter().copied())
+                .collect(),
+            aggregate: Vec::new(),
+            defuzzified: None,
+        };
+        // Otherwise the snapshot still has the output set of an earlier inference
+        if fired {
+            let units = |&(x, y): &(f32, f32)| (self.output.to_units(x), y);
+            trace.aggregate = snapshot.aggregate()[1..].iter().map(units).collect();
+            trace.defuzzified = Some(units(&snapshot.aggregate()[0]));
+        }
+        Ok((crisp, trace))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        funcs::{smf, tri, zmf, Mf},
+        fuzzy::{Defuzzifier, OutOfRange},
+        rules::very,
+        trace::NodeTrace,
+    };
+
+    fn x(term: &str) -> Term {
+        Var::new("X").term(term)
+    }
+
+    fn w(term: &str) -> Term {
+        Var::new("W").term(term)
+    }
+
+    fn diff(term: &str) -> Term {
+        Var::new("Diff").term(term)
+    }
+
+    /// Diff from X and W, whose terms all hold one half at zero.
+    fn builder(rules: Vec<(Term, Rule, f32)>) -> MamdaniBuilder {
+        Mamdani::builder()
+            .input(
+                Var::new("X"),
+                Fuzzy::new(
+                    Var::new("X"),
+                    HashMap::from([(x("N"), zmf(-1., 1.)), (x("P"), smf(-1., 1.))]),
+                    -1.0..1.,
+                ),
+            )
+            .input(
+                Var::new("W"),
+                Fuzzy::new(
+                    Var::new("W"),
+                    HashMap::from([(w("N"), zmf(-1., 1.)), (w("P"), smf(-1., 1.))]),
+                    -1.0..1.,
+                ),
+            )
+            .output(Fuzzy::new(
+                "Diff",
+                HashMap::from([
+                    (diff("NS"), tri(0., 2., 4.)),
+                    (diff("PS"), tri(6., 8., 10.)),
+                ]),
+                0.0..10.,
+            ))
+            .rules(rules)
+    }
+
+    /// NS when X is negative and PS when it is positive.
+    fn by_x() -> Vec<(Term, Rule, f32)> {
+        vec![
+            (diff("NS"), x("N").into(), 1.),
+            (diff("PS"), x("P").into(), 1.),
+        ]
+    }
+
+    #[test]
+    fn snapshots_count_outside_values_under_every_policy() {
+        let (temp, fan) = (Var::new("Temp"), Var::new("Fan"));
+        for policy in [
+            OutOfRange::Clamp,
+            OutOfRange::Saturate,
+            OutOfRange::Extrapolate,
+            OutOfRange::Flag,
+        ] {
+            let m = Mamdani::builder()
+                .input(
+                    temp.clone(),
+                    Fuzzy::new(
+                        &temp,
+                        [
+                            (temp.term("Cold"), zmf(0., 40.)),
+                            (temp.term("Hot"), smf(0., 40.)),
+                        ],
+                        0.0..40.,
+                    )
+                    .with_out_of_range(policy),
+                )
+                .output(Fuzzy::new(
+                    &fan,
+                    [
+                        (fan.term("Slow"), tri(0., 20., 50.)),
+                        (fan.term("Fast"), tri(50., 80., 100.)),
+                    ],
+                    0.0..100.,
+                ))
+                .text_rules(
+                    "IF Temp IS Cold THEN Fan IS Slow
+                     IF Temp IS Hot THEN Fan IS Fast",
+                )
+                .build()
+                .unwrap();
+            let mut snapshot = Snapshot::new();
+            for t in [50., -5., 20., 40.] {
+                let output = m.infer(&[(temp.clone(), t)], &mut snapshot);
+                let outside = !(0. ..=40.).contains(&t);
+                assert_eq!(
+                    output.is_err(),
+                    outside && policy == OutOfRange::Flag,
+                    "{:?} at {}",
+                    policy,
+                    t
+                );
+            }
+            let outside: Vec<(&Var, usize)> = snapshot.outside().collect();
+            assert_eq!(outside, [(&temp, 2)], "{:?}", policy);
+            assert_eq!(snapshot.input(&temp), Some(40.));
+        }
+    }
+
+    #[test]
+    fn rules_sharing_a_consequent_accumulate() {
+        let (ns, ps) = (diff("NS"), diff("PS"));
+        let rules = vec![
+            (ns.clone(), x("N").into(), 1.),
+            (ns, w("N").into(), 1.),
+            (ps, x("P").into(), 1.),
+        ];
+        let at_zero = [(Var::new("X"), 0.), (Var::new("W"), 0.)];
+        let single = builder(rules[1..].to_vec()).build().unwrap();
+        let single = single.infer(&at_zero, &mut Snapshot::new()).unwrap();
+        // Both NS rules hold one half: the more they add up, the lower the output
+        let [max, prob_or, bounded_sum] = [
+            Aggregation::Max,
+            Aggregation::ProbOr,
+            Aggregation::BoundedSum,
+        ]
+        .map(|accumulation| {
+            let m = builder(rules.clone())
+                .accumulation(accumulation)
+                .build()
+                .unwrap();
+            m.infer(&at_zero, &mut Snapshot::new()).unwrap()
+        });
+        assert_eq!(max, single);
+        assert!(prob_or < max, "{} vs {}", prob_or, max);
+        assert!(bounded_sum < prob_or, "{} vs {}", bounded_sum, prob_or);
+    }
+
+    #[test]
+    fn weights_scale_strengths() {
+        let mut rules = by_x();
+        rules[1].2 = 0.4;
+        let m = builder(rules).build().unwrap();
+        let finputs = m
+            .fuzzify(&[(Var::new("X"), 1.), (Var::new("W"), 0.)])
+            .unwrap();
+        assert_eq!(finputs[&x("P")], 1.);
+        assert!((m.fire(&finputs).unwrap()[1] - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn misfires_follow_the_policy() {
+        // Nothing fires while W is negative
+        let rules = vec![
+            (diff("NS"), x("N") & w("P"), 1.),
+            (diff("PS"), x("P") & w("P"), 1.),
+        ];
+        let at = |w| [(Var::new("X"), 1.), (Var::new("W"), w)];
+        let mut snapshot = Snapshot::new();
+        let hold = builder(rules.clone()).build().unwrap();
+        let y = hold.infer(&at(1.), &mut snapshot);
+        assert!(y.is_ok());
+        assert_eq!(hold.infer(&at(-1.), &mut snapshot), y);
+        assert_eq!(snapshot.misfires(), 1);
+
+        let default = builder(rules.clone())
+            .no_firing(NoFiring::Default(-1.))
+            .build()
+            .unwrap();
+        assert_eq!(default.infer(&at(-1.), &mut snapshot), Ok(-1.));
+        let error = builder(rules).no_firing(NoFiring::Error).build().unwrap();
+        assert_eq!(
+            error.infer(&at(-1.), &mut snapshot),
+            Err(FuzzyError::NoRuleFired)
+        );
+        assert_eq!(snapshot.misfires(), 3);
+    }
+
+    #[test]
+    fn sets_too_narrow_to_sample_misfire() {
+        // PS lies between two samples of the output, so it fires into nothing
+        let mut m = builder(by_x())
+            .output(Fuzzy::new(
+                "Diff",
+                HashMap::from([
+                    (diff("NS"), tri(0., 2., 4.)),
+                    (diff("PS"), tri(4.06, 4.08, 4.1)),
+                ]),
+                0.0..10.,
+            ))
+            .no_firing(NoFiring::Default(-1.))
+            .build()
+            .unwrap();
+        let mut snapshot = Snapshot::new();
+        for method in Defuzzifier::ALL {
+            m.output_mut().defuzzifier = method;
+            assert_eq!(
+                m.infer(&[(Var::new("X"), 1.), (Var::new("W"), 0.)], &mut snapshot),
+                Ok(-1.)
+            );
+        }
+        assert_eq!(snapshot.misfires(), Defuzzifier::ALL.len());
+        assert!(snapshot.aggregate().is_empty());
+
+        let x = [1., -1.];
+        let w = [0., 0.];
+        let inputs = [(Var::new("X"), &x[..]), (Var::new("W"), &w[..])];
+        let mut out = [0.; 2];
+        m.infer_batch(&inputs, &mut out, &mut snapshot).unwrap();
+        assert_eq!(out[0], -1.);
+        assert!(out[1] > 0.);
+        assert_eq!(snapshot.misfires(), Defuzzifier::ALL.len() + 1);
+    }
+
+    #[test]
+    fn norms_are_checked_when_changed() {
+        let mut m = builder(by_x()).build().unwrap();
+        let at = [(Var::new("X"), 0.4), (Var::new("W"), 0.)];
+        let before = m.infer(&at, &mut Snapshot::new()).unwrap();
+        let yager = Norm::Yager(0.);
+        assert_eq!(m.set_norm(yager), Err(FuzzyError::InvalidNorm(yager)));
+        assert_eq!(m.norm(), Norm::Zadeh);
+        assert_eq!(m.infer(&at, &mut Snapshot::new()), Ok(before));
+        m.set_norm(Norm::Lukasiewicz).unwrap();
+        assert_eq!(m.norm(), Norm::Lukasiewicz);
+    }
+
+    #[test]
+    fn bad_inputs_are_errors() {
+        let mut rules = by_x();
+        rules[0].1 = x("N") & w("N");
+        let m = builder(rules).build().unwrap();
+        let mut snapshot = Snapshot::new();
+        assert_eq!(
+            m.infer(&[(Var::new("X"), 0.), (Var::new("Y"), 1.)], &mut snapshot),
+            Err(FuzzyError::UnknownInput(Var::new("Y")))
+        );
+        assert_eq!(
+            m.infer(&[(Var::new("X"), 0.)], &mut snapshot),
+            Err(FuzzyError::MissingInput(Var::new("W")))
+        );
+    }
+
+    #[test]
+    fn builds_report_every_problem() {
+        let n = [(x("N"), zmf(-1., 1.))];
+        let errors = Mamdani::builder()
+            .input(
+                Var::new("X"),
+                Fuzzy::new(Var::new("X"), n.clone(), -1.0..1.),
+            )
+            .input(Var::new("X"), Fuzzy::new(Var::new("X"), n, 1.0..-1.))
+            .input(
+                Var::new("W"),
+                Fuzzy::new(
+                    Var::new("W"),
+                    HashMap::from([(w("N"), zmf(-1., 1.))]),
+                    5.0..5.,
+                ),
+            )
+            .output(Fuzzy::new(
+                "Diff",
+                [
+                    (diff("NS"), tri(0., 2., 4.)),
+                    (diff("PS"), tri(6., 8., 10.)),
+                    (diff("NS"), tri(0., 3., 4.)),
+                ],
+                0.0..10.,
+            ))
+            .weighted_rule(diff("NS"), x("N"), -0.5)
+            .weighted_rule(diff("NS"), w("N"), f32::NAN)
+            .rule(diff("NS"), x("P"))
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(errors.len(), 8, "{:?}", errors);
+        let mut expected = vec![
+            FuzzyError::DuplicateVariable(Var::new("X")),
+            FuzzyError::DuplicateTerm(Var::new("Diff"), diff("NS")),
+            FuzzyError::InvalidRange("X".to_string(), 1.0..-1.),
+            FuzzyError::InvalidRange("W".to_string(), 5.0..5.),
+            FuzzyError::InvalidWeight(-0.5),
+            FuzzyError::MissingMembership(x("P")),
+            FuzzyError::UncoveredOutput(diff("PS")),
+        ];
+        for e in &errors {
+            match e {
+                FuzzyError::InvalidWeight(w) if w.is_nan() => continue,
+                e => expected.retain(|x| x != e),
+            }
+        }
+        assert!(expected.is_empty(), "not reported: {:?}", expected);
+    }
+
+    #[test]
+    fn builds_need_an_output() {
+        let errors = Mamdani::builder()
+            .input(
+                Var::new("X"),
+                Fuzzy::new(
+                    Var::new("X"),
+                    HashMap::from([(x("N"), zmf(-1., 1.))]),
+                    -1.0..1.,
+                ),
+            )
+            .rule(diff("NS"), x("N"))
+            .build()
+            .err();
+        assert_eq!(errors, Some(vec![FuzzyError::MissingOutputVariable]));
+    }
+
+    #[test]
+    fn traces_follow_the_inference() {
+        let mut rules = by_x();
+        rules[1].1 = x("P") | !w("N");
+        let m = builder(rules.clone()).build().unwrap();
+        let twin = builder(rules).build().unwrap();
+        for (x, w) in [(-0.8, 0.1), (0.3, -0.6), (0., 0.)] {
+            let at = [(Var::new("X"), x), (Var::new("W"), w)];
+            let (crisp, trace) = m.infer_explained(&at, &mut Snapshot::new()).unwrap();
+            assert_eq!(twin.infer(&at, &mut Snapshot::new()), Ok(crisp));
+            let finputs = twin.fuzzify(&at).unwrap();
+            let strengths: Vec<f32> = trace.rules.iter().map(|r| r.strength).collect();
+            assert_eq!(strengths, twin.fire(&finputs).unwrap());
+            assert_eq!(trace.defuzzified.map(|p| p.0), Some(crisp));
+            let terms: Vec<Term> = trace.outputs.iter().map(|(t, _)| t.clone()).collect();
+            assert_eq!(terms, m.output().terms());
+        }
+    }
+
+    #[test]
+    fn traces_read_the_compiled_rules() {
+        fn values(trace: &NodeTrace, out: &mut Vec<(String, f32)>) {
+            out.push((format!("{:?}", trace.node), trace.value));
+            trace.children.iter().for_each(|c| values(c, out));
+        }
+        let mut rules = by_x();
+        rules[0].1 = very(x("N")) & !w("P");
+        rules[1].1 = (x("P") | w("N")).using(Norm::Product) & w("N");
+        let m = builder(rules).norm(Norm::Einstein).build().unwrap();
+        for (x, w) in [(-0.8, 0.1), (0.3, -0.6), (0., 0.)] {
+            let at = [(Var::new("X"), x), (Var::new("W"), w)];
+            let (_, trace) = m.infer_explained(&at, &mut Snapshot::new()).unwrap();
+            let finputs = m.fuzzify(&at).unwrap();
+            for ((_, rule, _), traced) in m.rules().iter().zip(&trace.rules) {
+                let (mut compiled, mut walked) = (Vec::new(), Vec::new());
+                values(&traced.antecedent, &mut compiled);
+                values(&rule.trace(&finputs, m.norm()).unwrap(), &mut walked);
+                assert_eq!(compiled.len(), walked.len());
+                for ((n, a), (m, b)) in compiled.iter().zip(&walked) {
+                    assert_eq!(n, m);
+                    assert!((a - b).abs() < 1e-6, "{} is {} instead of {}", n, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn builds_reject_singleton_outputs() {
+        let errors = builder(by_x())
+            .output(Fuzzy::new(
+                "Diff",
+                [
+                    (diff("NS"), tri(0., 2., 4.)),
+                    (diff("PS"), Mf::Singleton { c: 8. }),
+                ],
+                0.0..10.,
+            ))
+            .build()
+            .err();
+        assert_eq!(errors, Some(vec![FuzzyError::SingletonOutput(diff("PS"))]));
+    }
+
+    fn controller() -> Mamdani {
+        let (temp, hum, fan) = (Var::new("Temp"), Var::new("Hum"), Var::new("Fan"));
+        Mamdani::builder()
+            .input(
+                temp.clone(),
+                Fuzzy::new(
+                    &temp,
+                    [
+                        (temp.term("Cold"), zmf(0., 40.)),
+                        (temp.term("Hot"), smf(0., 40.)),
+                    ],
+                    0.0..40.,
+                ),
+            )
+            .input(
+                hum.clone(),
+                Fuzzy::new(
+                    &hum,
+                    [
+                        (hum.term("Dry"), zmf(0., 100.)),
+                        (hum.term("Wet"), smf(0., 100.)),
+                    ],
+                    0.0..100.,
+                ),
+            )
+            .output(Fuzzy::new(
+                &fan,
+                [
+                    (fan.term("Slow"), tri(0., 20., 50.)),
+                    (fan.term("Fast"), tri(50., 80., 100.)),
+                ],
+                0.0..100.,
+            ))
+            .text_rules(
+                "IF Temp IS Cold OR Hum IS VERY Dry THEN Fan IS Slow
+                 IF Temp IS Hot AND Hum IS Wet THEN Fan IS Fast USING PRODUCT",
+            )
+            .no_firing(NoFiring::Default(-1.))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn batches_match_single_inferences() {
+        let m = controller();
+        let temp: Vec<f32> = (0..300).map(|i| (i % 41) as f32).collect();
+        let hum: Vec<f32> = (0..300).map(|i| (i * 7 % 101) as f32).collect();
+        let inputs = [(Var::new("Hum"), &hum[..]), (Var::new("Temp"), &temp[..])];
+        let mut serial = vec![0.; 300];
+        let mut parallel = vec![0.; 300];
+        let mut snapshot = Snapshot::new();
+        m.infer_batch(&inputs, &mut serial, &mut snapshot).unwrap();
+        m.infer_batch_parallel(&inputs, &mut parallel, 3, &mut snapshot)
+            .unwrap();
+        assert_eq!(serial, parallel);
+        let mut single = Snapshot::new();
+        for ((&t, &h), &y) in temp.iter().zip(&hum).zip(&serial) {
+            let exact = m.infer(&[(Var::new("Temp"), t), (Var::new("Hum"), h)], &mut single);
+            assert_eq!(exact.unwrap(), y);
+        }
+        assert_eq!(snapshot.misfires(), 2 * single.misfires());
+    }
+
+    #[test]
+    fn batches_check_their_columns() {
+        let m = controller();
+        let (short, long) = ([0.; 2], [0.; 3]);
+        let mut out = [0.; 3];
+        assert_eq!(
+            m.infer_batch(&[(Var::new("Temp"), &long)], &mut out, &mut Snapshot::new()),
+            Err(FuzzyError::MissingInput(Var::new("Hum")))
+        );
+        assert_eq!(
+            m.infer_batch(
+                &[(Var::new("Temp"), &long), (Var::new("Hum"), &short)],
+                &mut out,
+                &mut Snapshot::new()
+            ),
+            Err(FuzzyError::ColumnLength(Var::new("Hum"), 2, 3))
+        );
+    }
+
+    #[test]
+    fn threads_share_one_controller() {
+        let m = controller();
+        let rows: Vec<[(Var, f32); 2]> = (0..40)
+            .map(|i| {
+                [
+                    (Var::new("Temp"), i as f32),
+                    (Var::new("Hum"), (i * 7 % 101) as f32),
+                ]
+            })
+            .collect();
+        let serial: Vec<f32> = rows
+            .iter()
+            .map(|r| m.infer(r, &mut Snapshot::new()).unwrap())
+            .collect();
+        std::thread::scope(|s| {
+            let workers: Vec<_> = (0..3)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut snapshot = Snapshot::new();
+                        let outputs: Vec<f32> = rows
+                            .iter()
+                            .map(|r| m.infer(r, &mut snapshot).unwrap())
+                            .collect();
+                        outputs
+                    })
+                })
+                .collect();
+            for w in workers {
+                assert_eq!(w.join().unwrap(), serial);
+            }
+        });
     }
 
-    pub fn infer(&mut self, inputs: &[(InputType, f32)]) -> f32 {
-        let finputs = self.fuzzify(inputs);
-        // println!("{:?}", finputs);
-        let mut outputs = HashMap::new();
-        // println!("{:?}", finputs);
-        for (out, rule) in self.rules.iter() {
-            let a = self.resolve(rule, &finputs);
-            // println!("{:?}", out);
-            // println!("{:?}", a);
-            outputs.insert(*out, a);
+    #[test]
+    fn snapshots_record_what_happened() {
+        let m = controller();
+        let (temp, hum) = (Var::new("Temp"), Var::new("Hum"));
+        let mut snapshot = Snapshot::new();
+        for t in [50., 20.] {
+            m.infer(&[(temp.clone(), t), (hum.clone(), 30.)], &mut snapshot)
+                .unwrap();
         }
-        // println!("{:?}", outputs);
-        self.output.defuzzify(outputs)
+        assert_eq!(snapshot.input(&temp), Some(20.));
+        let outside: HashMap<&Var, usize> = snapshot.outside().collect();
+        assert_eq!(outside, HashMap::from([(&temp, 1), (&hum, 0)]));
+        assert_eq!(snapshot.aggregate().len(), m.output().resolution() + 1);
     }
 }
@@ -0,0 +1,511 @@
+use crate::{
+    error::FuzzyError,
+    rules::{Hedge, Norm, Ordinal, Rule},
+    variables::{Registry, Term},
+};
+
+/// Parses one rule written as text, e.g.
+/// `IF Vx IS P AND (Th IS N OR NOT X IS Z) THEN Diff IS PM WITH 0.5`.
+///
+/// `NOT` binds tighter than `AND`, which binds tighter than `OR`. `X IS NOT N`
+/// is short for `NOT X IS N`. Inputs can also be compared by the order of their
+/// terms, as in `X IS AT LEAST Z`, `X IS AT MOST Z` or `X IS BETWEEN N AND Z`.
+/// Hedges go before the term, `Th IS VERY P`, or before a whole condition,
+/// `SOMEWHAT (X IS N AND Th IS P)`. `USING` after the consequent picks the norm
+/// of the rule's ANDs and ORs, as in `USING PRODUCT` or `USING YAGER 2`, and after
+/// a parenthesised group the norm of the group. The `WITH` weight is optional and defaults to 1.
+/// Variables and terms are looked up in `registry`.
+pub fn parse_rule(text: &str, registry: &Registry) -> Result<(Term, Rule, f32), FuzzyError> {
+    Parser::new(text, 1, registry).statement()
+}
+
+/// Parses one rule per line, skipping blank lines and `#` comments.
+pub fn parse_rules(
+    text: &str,
+    registry: &Registry,
+) -> Result<Vec<(Term, Rule, f32)>, Vec<FuzzyError>> {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        if line.trim().is_empty() {
+            continue;
+        }
+        match Parser::new(line, n + 1, registry).statement() {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(rules)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Prints a rule back in the syntax read by [`parse_rule`].
+pub fn format_rule(out: &Term, rule: &Rule, weight: f32) -> String {
+    let norm = rule.norm();
+    let mut text = format!("IF {} THEN {} IS {}", rule.text(norm), out.var(), out);
+    if let Some(n) = norm {
+        text += &format!(" USING {}", n);
+    }
+    if weight != 1. {
+        text += &format!(" WITH {}", weight);
+    }
+    text
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+}
+
+struct Parser<'a> {
+    registry: &'a Registry,
+    /// Tokens with their 1-based column.
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    line: usize,
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &str, line: usize, registry: &'a Registry) -> Self {
+        let mut tokens = Vec::new();
+        let mut word: Option<(String, usize)> = None;
+        let mut end = 1;
+        for (col, c) in text.chars().enumerate().map(|(i, c)| (i + 1, c)) {
+            end = col + 1;
+            if c.is_whitespace() || c == '(' || c == ')' {
+                if let Some((w, start)) = word.take() {
+                    tokens.push((Token::Word(w), start));
+                }
+                match c {
+                    '(' => tokens.push((Token::Open, col)),
+                    ')' => tokens.push((Token::Close, col)),
+                    _ => {}
+                }
+            } else {
+                word.get_or_insert_with(|| (String::new(), col)).0.push(c);
+            }
+        }
+        if let Some((w, start)) = word {
+            tokens.push((Token::Word(w), start));
+        }
+        Parser {
+            registry,
+            tokens,
+            next: 0,
+            line,
+            end,
+        }
+    }
+
+    fn error(&self, column: usize, message: impl Into<String>) -> FuzzyError {
+        FuzzyError::Parse(self.line, column, message.into())
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |(_, c)| *c)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(t, _)| t)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), FuzzyError> {
+        if self.peek_keyword(keyword) {
+            self.next += 1;
+            Ok(())
+        } else {
+            Err(self.error(self.column(), format!("expected {}", keyword)))
+        }
+    }
+
+    fn word(&mut self, what: &str) -> Result<(String, usize), FuzzyError> {
+        match self.tokens.get(self.next) {
+            Some((Token::Word(w), col)) => {
+                self.next += 1;
+                Ok((w.clone(), *col))
+            }
+            _ => Err(self.error(self.column(), format!("expected {}", what))),
+        }
+    }
+
+    fn variable(&mut self, what: &str) -> Result<String, FuzzyError> {
+        let (variable, col) = self.word(&format!("{} variable", what))?;
+        if self.registry.get(&variable).is_none() {
+            return Err(self.error(col, format!("unknown {} variable `{}`", what, variable)));
+        }
+        Ok(variable)
+    }
+
+    fn term(&mut self, variable: &str, what: &str) -> Result<Term, FuzzyError> {
+        let (term, col) = self.word(&format!("{} term", what))?;
+        self.registry
+            .term(variable, &term)
+            .ok_or_else(|| self.error(col, format!("unknown term `{}` for {}", term, variable)))
+    }
+
+    /// Reads `VAR IS [NOT] TERM`, or an ordinal condition such as `VAR IS AT LEAST TERM`.
+    fn condition(&mut self) -> Result<Rule, FuzzyError> {
+        let variable = self.variable("input")?;
+        self.keyword("IS")?;
+        let negated = self.peek_keyword("NOT");
+        if negated {
+            self.next += 1;
+        }
+        let mut hedges = Vec::new();
+        while let Some(h) = self.hedge() {
+            hedges.push(h);
+        }
+        let col = self.column();
+        let ordinal = if self.peek_keyword("AT") {
+            self.next += 1;
+            if self.peek_keyword("LEAST") {
+                self.next += 1;
+                Some(Ordinal::AtLeast(self.term(&variable, "input")?))
+            } else {
+                self.keyword("MOST")
+                    .map_err(|_| self.error(self.column(), "expected LEAST or MOST"))?;
+                Some(Ordinal::AtMost(self.term(&variable, "input")?))
+            }
+        } else if self.peek_keyword("BETWEEN") {
+            self.next += 1;
+            let low = self.term(&variable, "input")?;
+            self.keyword("AND")?;
+            Some(Ordinal::Between(low, self.term(&variable, "input")?))
+        } else {
+            None
+        };
+        let mut rule = match ordinal {
+            Some(o) => self
+                .registry
+                .ordinal(o)
+                .ok_or_else(|| self.error(col, "the range of terms is empty"))?,
+            None => self.term(&variable, "input")?.into(),
+        };
+        for h in hedges.into_iter().rev() {
+            rule = h.of(rule);
+        }
+        Ok(if negated { !rule } else { rule })
+    }
+
+    /// Takes a hedge keyword if there is one next.
+    fn hedge(&mut self) -> Option<Hedge> {
+        let hedge = Hedge::ALL
+            .into_iter()
+            .find(|h| self.peek_keyword(h.name()))?;
+        self.next += 1;
+        Some(hedge)
+    }
+
+    /// Applies a `USING` norm to `rule` if there is one next.
+    fn using(&mut self, rule: Rule) -> Result<Rule, FuzzyError> {
+        if !self.peek_keyword("USING") {
+            return Ok(rule);
+        }
+        self.next += 1;
+        let (name, col) = self.word("norm")?;
+        let norm = Norm::ALL
+            .into_iter()
+            .find(|n| n.name().eq_ignore_ascii_case(&name))
+            .ok_or_else(|| self.error(col, format!("unknown norm `{}`", name)))?;
+        let norm = match norm {
+            Norm::Hamacher(_) | Norm::Yager(_) => {
+                let (x, col) = self.word("norm parameter")?;
+                let x = x
+                    .parse()
+                    .map_err(|_| self.error(col, format!("invalid norm parameter `{}`", x)))?;
+                match norm {
+                    Norm::Hamacher(_) => Norm::Hamacher(x),
+                    _ => Norm::Yager(x),
+                }
+            }
+            n => n,
+        };
+        if !norm.is_valid() {
+            return Err(self.error(col, format!("invalid parameter for {}", norm)));
+        }
+        Ok(rule.using(norm))
+    }
+
+    fn statement(&mut self) -> Result<(Term, Rule, f32), FuzzyError> {
+        self.keyword("IF")?;
+        let rule = self.or()?;
+        self.keyword("THEN")?;
+        let variable = self.variable("output")?;
+        self.keyword("IS")?;
+        if self.peek_keyword("NOT") {
+            return Err(self.error(self.column(), "an output cannot be negated"));
+        }
+        let out = self.term(&variable, "output")?;
+        let rule = self.using(rule)?;
+        let mut weight = 1.;
+        if self.peek_keyword("WITH") {
+            self.next += 1;
+            let (w, col) = self.word("weight")?;
+            weight = w
+                .parse()
+                .map_err(|_| self.error(col, format!("invalid weight `{}`", w)))?;
+        }
+        if self.next < self.tokens.len() {
+            return Err(self.error(self.column(), "expected end of rule"));
+        }
+        Ok((out, rule, weight))
+    }
+
+    fn or(&mut self) -> Result<Rule, FuzzyError> {
+        let mut rule = self.and()?;
+        while self.peek_keyword("OR") {
+            self.next += 1;
+            rule = rule | self.and()?;
+        }
+        Ok(rule)
+    }
+
+    fn and(&mut self) -> Result<Rule, FuzzyError> {
+        let mut rule = self.unary()?;
+        while self.peek_keyword("AND") {
+            self.next += 1;
+            rule = rule & self.unary()?;
+        }
+        Ok(rule)
+    }
+
+    fn unary(&mut self) -> Result<Rule, FuzzyError> {
+        if self.peek_keyword("NOT") {
+            self.next += 1;
+            return Ok(!self.unary()?);
+        }
+        if let Some(h) = self.hedge() {
+            return Ok(h.of(self.unary()?));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.next += 1;
+            let rule = self.or()?;
+            if self.peek() != Some(&Token::Close) {
+                return Err(self.error(self.column(), "expected `)`"));
+            }
+            self.next += 1;
+            return self.using(rule);
+        }
+        self.condition()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        program::Program,
+        rules::{Op, RuleNode},
+        variables::Var,
+    };
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        for (var, terms) in [
+            ("X", ["N", "Z", "P"].as_slice()),
+            ("Th", &["N", "Z", "P"]),
+            ("Out", &["Lo", "Hi"]),
+        ] {
+            registry.define(var, terms).unwrap();
+        }
+        registry
+    }
+
+    fn x(term: &str) -> Rule {
+        Var::new("X").term(term).into()
+    }
+
+    fn th(term: &str) -> Rule {
+        Var::new("Th").term(term).into()
+    }
+
+    /// Each construct of the syntax with the tree it should give.
+    fn examples() -> Vec<(&'static str, Rule, f32)> {
+        let r = registry();
+        vec![
+            ("IF X IS N THEN Out IS Hi", x("N"), 1.),
+            ("IF NOT X IS N THEN Out IS Hi", !x("N"), 1.),
+            ("IF X IS NOT N THEN Out IS Hi", !x("N"), 1.),
+            (
+                "IF X IS N OR X IS Z AND Th IS P THEN Out IS Hi",
+                x("N") | (x("Z") & th("P")),
+                1.,
+            ),
+            (
+                "IF NOT X IS N AND Th IS P THEN Out IS Hi",
+                !x("N") & th("P"),
+                1.,
+            ),
+            (
+                "IF (X IS N OR X IS Z) AND NOT (Th IS P OR Th IS Z) THEN Out IS Hi",
+                (x("N") | x("Z")) & !(th("P") | th("Z")),
+                1.,
+            ),
+            (
+                "IF X IS VERY SOMEWHAT N THEN Out IS Hi",
+                Hedge::Very.of(Hedge::Somewhat.of(x("N"))),
+                1.,
+            ),
+            (
+                "IF INDEED (X IS N AND Th IS P) THEN Out IS Hi",
+                Hedge::Indeed.of(x("N") & th("P")),
+                1.,
+            ),
+            (
+                "IF X IS AT LEAST Z THEN Out IS Hi",
+                r.ordinal(Ordinal::AtLeast(Var::new("X").term("Z")))
+                    .unwrap(),
+                1.,
+            ),
+            (
+                "IF X IS NOT BETWEEN N AND Z THEN Out IS Hi",
+                !r.between(Var::new("X").term("N"), Var::new("X").term("Z"))
+                    .unwrap(),
+                1.,
+            ),
+            (
+                "if X is N and (Th is Z) then Out is Lo",
+                x("N") & th("Z"),
+                1.,
+            ),
+            (
+                "IF X IS N AND Th IS P THEN Out IS Hi USING PRODUCT",
+                (x("N") & th("P")).using(Norm::Product),
+                1.,
+            ),
+            (
+                "IF (X IS N OR Th IS P) USING YAGER 3 AND X IS Z THEN Out IS Lo",
+                (x("N") | th("P")).using(Norm::Yager(3.)) & x("Z"),
+                1.,
+            ),
+            ("IF X IS P THEN Out IS Lo WITH 0.25", x("P"), 0.25),
+        ]
+    }
+
+    /// Rules hold function pointers, so trees are compared by their printout,
+    /// which parenthesises every child that binds looser than its parent.
+    fn same((out, rule, weight): &(Term, Rule, f32), expected: &(Term, Rule, f32)) -> bool {
+        *out == expected.0 && rule.to_string() == expected.1.to_string() && *weight == expected.2
+    }
+
+    #[test]
+    fn constructs_build_their_trees() {
+        let r = registry();
+        for (text, rule, weight) in examples() {
+            let out = if text.contains("Lo") { "Lo" } else { "Hi" };
+            let expected = (Var::new("Out").term(out), rule, weight);
+            let parsed = parse_rule(text, &r).unwrap();
+            assert!(same(&parsed, &expected), "{} gave {}", text, parsed.1);
+        }
+    }
+
+    #[test]
+    fn printed_rules_parse_back() {
+        let r = registry();
+        let out = Var::new("Out").term("Hi");
+        let built = [
+            ((x("N") | th("P")).using(Norm::Yager(2.)) & x("Z")).using(Norm::Product),
+            Hedge::Slightly.of(!(x("N") & (th("Z") | th("P")))),
+            (x("N") & th("N")) | (x("P") & !(th("P") | x("Z"))),
+            (x("N") & th("N")) | (x("P") & Hedge::Extremely.of(th("P"))),
+            x("N") & (th("N") & x("Z")),
+        ];
+        let parsed = examples()
+            .into_iter()
+            .map(|(_, rule, weight)| (rule, weight));
+        for (rule, weight) in parsed.chain(built.into_iter().map(|rule| (rule, 0.5))) {
+            let text = format_rule(&out, &rule, weight);
+            let again = parse_rule(&text, &r).unwrap_or_else(|e| panic!("{}: {}", text, e));
+            assert!(
+                same(&again, &(out.clone(), rule.clone(), weight)),
+                "{}",
+                text
+            );
+            // Display prints the same antecedent without the rule's USING
+            if rule.norm().is_none() {
+                assert!(text.starts_with(&format!("IF {} THEN", rule)), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn inherited_norms_print_as_the_enclosing_group() {
+        let r = registry();
+        let out = Var::new("Out").term("Hi");
+        // An OR without a norm under an AND with one, as only the tree fields can build
+        let rule = Rule {
+            val: RuleNode::Op(Op::And(Some(Norm::Product))),
+            left: Some(Box::new(x("N") | th("P"))),
+            right: Some(Box::new(x("Z"))),
+        };
+        let text = format_rule(&out, &rule, 1.);
+        assert_eq!(
+            text,
+            "IF (X IS N OR Th IS P) AND X IS Z THEN Out IS Hi USING PRODUCT"
+        );
+        let (_, again, _) = parse_rule(&text, &r).unwrap();
+        assert_eq!(
+            again.to_string(),
+            ((x("N") | th("P")) & x("Z"))
+                .using(Norm::Product)
+                .to_string()
+        );
+        let finputs = HashMap::from([
+            (Var::new("X").term("N"), 0.6),
+            (Var::new("X").term("Z"), 0.5),
+            (Var::new("Th").term("P"), 0.3),
+        ]);
+        for norm in [Norm::Zadeh, Norm::Lukasiewicz] {
+            let strength = rule.resolve(&finputs, norm).unwrap();
+            assert!((strength - 0.36).abs() < 1e-6, "{} gave {}", norm, strength);
+            assert_eq!(again.resolve(&finputs, norm).unwrap(), strength);
+            let program = Program::compile([(&rule, 1.)], norm).unwrap();
+            assert_eq!(program.fire(&finputs).unwrap(), [strength]);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_their_token() {
+        let r = registry();
+        for (text, column) in [
+            ("IF Q IS N THEN Out IS Hi", 4),
+            ("IF X IS Q THEN Out IS Hi", 9),
+            ("IF X IS N Out IS Hi", 11),
+            ("IF (X IS N THEN Out IS Hi", 12),
+            ("IF X IS N THEN Out IS N", 23),
+            ("IF X IS N THEN Out IS Hi WITH x", 31),
+            ("IF X IS N THEN Out IS Hi USING YAGER x", 38),
+            ("IF X IS N THEN Out IS Hi USING YAGER 0", 32),
+            ("IF X IS N THEN Out IS Hi WITH 0.5 Th", 35),
+            ("IF X IS N THEN Out IS Hi )", 26),
+        ] {
+            match parse_rule(text, &r) {
+                Err(FuzzyError::Parse(1, col, _)) => assert_eq!(col, column, "{}", text),
+                other => panic!("{} gave {:?}", text, other.map(|r| r.1.to_string())),
+            }
+        }
+        let errors = parse_rules(
+            "IF X IS N THEN Out IS Hi # comment\n\nIF X IS N THEN Out IS Q",
+            &r,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(errors[..], [FuzzyError::Parse(3, 23, _)]),
+            "{:?}",
+            errors
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::ops::Range;
+
+use crate::{
+    error::FuzzyError,
+    funcs::{cliff, gauss2mf, gbell, mount, piecewise, pimf, smf, tri, zmf, Mf},
+    variables::{Term, Var},
+};
+
+/// Shape of the terms of a [`Partition`].
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Shape {
+    /// Triangles, with linear shoulders at both ends. A Ruspini partition.
+    Tri,
+    /// Pi-shaped bumps, with Z and S shoulders at both ends. A Ruspini partition.
+    Smooth,
+    /// Gaussians, each side reaching one half midway to its neighbour.
+    Gauss,
+    /// Generalized bells reaching one half midway to the nearer neighbour.
+    Bell,
+}
+
+impl Shape {
+    /// Whether the memberships of every point sum to one.
+    pub fn is_ruspini(&self) -> bool {
+        matches!(self, Shape::Tri | Shape::Smooth)
+    }
+}
+
+/// Terms of one shape peaking at the given centers, named from most negative
+/// to most positive.
+///
+/// Iterates as `(name, function)` pairs, ready for [`Fuzzy::new`](crate::fuzzy::Fuzzy::new).
+#[derive(Debug, Clone)]
+pub struct Partition {
+    shape: Shape,
+    centers: Vec<f32>,
+    names: Vec<String>,
+}
+
+impl Partition {
+    /// `n` terms with evenly spaced centers, the first and last on the ends of `range`.
+    pub fn even(shape: Shape, range: &Range<f32>, n: usize) -> Result<Self, FuzzyError> {
+        if range.is_empty() || !range.start.is_finite() || !range.end.is_finite() {
+            return Err(FuzzyError::InvalidRange(
+                "partition".to_string(),
+                range.clone(),
+            ));
+        }
+        let step = (range.end - range.start) / (n.max(2) - 1) as f32;
+        let centers = if n == 1 {
+            vec![(range.start + range.end) / 2.]
+        } else {
+            (0..n).map(|i| range.start + i as f32 * step).collect()
+        };
+        Partition::at(shape, centers)
+    }
+
+    /// Terms centered on `centers`, in increasing order. The centers must be
+    /// finite and distinct, since each term reaches out to its neighbours.
+    pub fn at(shape: Shape, centers: impl IntoIterator<Item = f32>) -> Result<Self, FuzzyError> {
+        let mut centers: Vec<f32> = centers.into_iter().collect();
+        centers.sort_by(f32::total_cmp);
+        if let Some(c) = centers.iter().find(|c| !c.is_finite()) {
+            return Err(FuzzyError::InvalidPartition(format!(
+                "center {} is not finite",
+                c
+            )));
+        }
+        if let Some(w) = centers.windows(2).find(|w| w[0] == w[1]) {
+            return Err(FuzzyError::InvalidPartition(format!(
+                "center {} is given twice",
+                w[0]
+            )));
+        }
+        Ok(Partition {
+            shape,
+            names: names(centers.len()),
+            centers,
+        })
+    }
+
+    /// Replaces the generated names, in the order of the centers, one per center.
+    pub fn named(
+        mut self,
+        names: impl IntoIterator<Item = impl ToString>,
+    ) -> Result<Self, FuzzyError> {
+        let names: Vec<String> = names.into_iter().map(|n| n.to_string()).collect();
+        if names.len() != self.centers.len() {
+            return Err(FuzzyError::InvalidPartition(format!(
+                "{} names for {} centers",
+                names.len(),
+                self.centers.len()
+            )));
+        }
+        let mut earlier = names.iter().enumerate().map(|(i, n)| (n, &names[..i]));
+        if let Some((name, _)) = earlier.find(|(n, before)| before.contains(n)) {
+            return Err(FuzzyError::InvalidPartition(format!(
+                "{} is named twice",
+                name
+            )));
+        }
+        self.names = names;
+        Ok(self)
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn centers(&self) -> &[f32] {
+        &self.centers
+    }
+
+    pub fn functions(&self) -> Vec<(String, Mf)> {
+        let c = &self.centers;
+        let n = c.len();
+        let mfs = (0..n).map(|i| {
+            if n == 1 {
+                return piecewise([(c[0], 1.)]);
+            }
+            // Neighbouring centers, mirrored at the ends
+            let left = if i > 0 {
+                c[i - 1]
+            } else {
+                2. * c[i] - c[i + 1]
+            };
+            let right = if i + 1 < n {
+                c[i + 1]
+            } else {
+                2. * c[i] - left
+            };
+            match (self.shape, i) {
+                (Shape::Tri, 0) => cliff(c[0], c[1]),
+                (Shape::Tri, i) if i == n - 1 => mount(left, c[i]),
+                (Shape::Tri, i) => tri(left, c[i], right),
+                (Shape::Smooth, 0) => zmf(c[0], c[1]),
+                (Shape::Smooth, i) if i == n - 1 => smf(left, c[i]),
+                (Shape::Smooth, i) => pimf(left, c[i], c[i], right),
+                (Shape::Gauss, i) => {
+                    // exp(-h^2 / 8 sigma^2) = 1/2 at half the gap h
+                    let sigma = |h: f32| h / (2. * (2. * 2f32.ln()).sqrt());
+                    gauss2mf(c[i], sigma(c[i] - left), c[i], sigma(right - c[i]))
+                }
+                (Shape::Bell, i) => gbell((c[i] - left).min(right - c[i]) / 2., 2., c[i]),
+            }
+        });
+        self.names.iter().cloned().zip(mfs).collect()
+    }
+
+    /// The functions keyed by terms of `var`, for a [`Fuzzy<Term>`](crate::fuzzy::Fuzzy).
+    pub fn terms(&self, var: impl Into<Var>) -> Vec<(Term, Mf)> {
+        let var = var.into();
+        self.functions()
+            .into_iter()
+            .map(|(name, mf)| (var.term(&name), mf))
+            .collect()
+    }
+}
+
+impl IntoIterator for Partition {
+    type Item = (String, Mf);
+    type IntoIter = std::vec::IntoIter<(String, Mf)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.functions().into_iter()
+    }
+}
+
+/// Symmetric names for `n` terms, e.g. `NL NM NS Z PS PM PL` for seven.
+/// The middle term of an odd count is `Z`; past three per side they are numbered.
+pub fn names(n: usize) -> Vec<String> {
+    let side: Vec<String> = match n / 2 {
+        0 => vec![],
+        1 => vec!["".into()],
+        2 => vec!["L".into(), "S".into()],
+        3 => vec!["L".into(), "M".into(), "S".into()],
+        k => (1..=k).rev().map(|i| i.to_string()).collect(),
+    };
+    let negative = side.iter().map(|s| format!("N{}", s));
+    let zero = (n % 2 == 1).then(|| "Z".to_string());
+    let positive = side.iter().rev().map(|s| format!("P{}", s));
+    negative.chain(zero).chain(positive).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sums(partition: &Partition, range: Range<f32>) -> impl Iterator<Item = f32> {
+        let functions = partition.functions();
+        (0..=200).map(move |i| {
+            let x = range.start + (range.end - range.start) * i as f32 / 200.;
+            functions.iter().map(|(_, mf)| mf.eval(x)).sum()
+        })
+    }
+
+    #[test]
+    fn names_are_symmetric() {
+        assert_eq!(names(1), ["Z"]);
+        assert_eq!(names(2), ["N", "P"]);
+        assert_eq!(names(3), ["N", "Z", "P"]);
+        assert_eq!(names(4), ["NL", "NS", "PS", "PL"]);
+        assert_eq!(names(7), ["NL", "NM", "NS", "Z", "PS", "PM", "PL"]);
+        assert_eq!(
+            names(9),
+            ["N4", "N3", "N2", "N1", "Z", "P1", "P2", "P3", "P4"]
+        );
+    }
+
+    #[test]
+    fn ruspini_shapes_sum_to_one() {
+        for shape in [Shape::Tri, Shape::Smooth] {
+            assert!(shape.is_ruspini());
+            let even = Partition::even(shape, &(-10.0..10.), 5).unwrap();
+            let custom = Partition::at(shape, [-10., -4., -2., 0., 2., 4., 10.]).unwrap();
+            for sum in sums(&even, -12.0..12.).chain(sums(&custom, -12.0..12.)) {
+                assert!((sum - 1.).abs() < 1e-5, "{:?} sums to {}", shape, sum);
+            }
+        }
+    }
+
+    #[test]
+    fn terms_peak_at_their_centers() {
+        for shape in [Shape::Tri, Shape::Smooth, Shape::Gauss, Shape::Bell] {
+            let partition = Partition::at(shape, [0., 1., 3., 6.]).unwrap();
+            for ((_, mf), &c) in partition.functions().iter().zip(partition.centers()) {
+                assert!((mf.eval(c) - 1.).abs() < 1e-5, "{:?} at {}", shape, c);
+            }
+        }
+        let gauss = Partition::even(Shape::Gauss, &(0.0..1.), 3)
+            .unwrap()
+            .functions();
+        assert!((gauss[0].1.eval(0.25) - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn partitions_check_centers_and_names() {
+        let invalid = |result: Result<Partition, FuzzyError>| {
+            matches!(result, Err(FuzzyError::InvalidPartition(_)))
+        };
+        assert!(invalid(Partition::at(Shape::Tri, [0., 2., 2.])));
+        assert!(invalid(Partition::at(Shape::Tri, [0., f32::NAN])));
+        assert!(matches!(
+            Partition::even(Shape::Tri, &(1.0..1.), 3),
+            Err(FuzzyError::InvalidRange(..))
+        ));
+        let partition = || Partition::at(Shape::Tri, [2., 0., 1.]).unwrap();
+        assert!(invalid(partition().named(["Low", "High"])));
+        assert!(invalid(partition().named(["Low", "Mid", "High", "Top"])));
+        assert!(invalid(partition().named(["Low", "Mid", "Low"])));
+        let named = partition().named(["Low", "Mid", "High"]).unwrap();
+        assert_eq!(named.names(), ["Low", "Mid", "High"]);
+        assert_eq!(named.centers(), [0., 1., 2.]);
+    }
+}
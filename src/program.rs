@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::FuzzyError,
+    fuzzy::Fuzzy,
+    rules::{Hedge, Norm, Op, Rule, RuleNode},
+    snapshot::Snapshot,
+    trace::NodeTrace,
+    variables::{Term, Var},
+};
+
+/// One step of a [`Program`], reading earlier slots and writing the next one.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+enum Instr {
+    /// Best of two slots, for ordinal conditions.
+    Max(usize, usize),
+    Hedge(Hedge, usize),
+    Not(usize),
+    /// The first field indexes the norms of the program.
+    And(usize, usize, usize),
+    Or(usize, usize, usize),
+}
+
+/// A rule base compiled once into a flat list of instructions over dense slots.
+///
+/// The first slots hold the memberships of the input terms in the order of
+/// [`terms`](Self::terms), and every instruction writes the slot after them.
+/// Identical sub-expressions, up to the order of the operands of AND and OR,
+/// are computed once.
+#[derive(Debug, Clone)]
+pub struct Program {
+    terms: Vec<Term>,
+    /// Slot of each term.
+    index: HashMap<Term, usize>,
+    /// Slot written by each instruction.
+    seen: HashMap<Instr, usize>,
+    /// Norm of the AND and OR without their own.
+    norm: Norm,
+    /// Whether a rule reads the term.
+    used: Vec<bool>,
+    norms: Vec<Norm>,
+    instrs: Vec<Instr>,
+    /// Slot and weight of each rule.
+    rules: Vec<(usize, f32)>,
+}
+
+struct Compiler {
+    index: HashMap<Term, usize>,
+    seen: HashMap<Instr, usize>,
+    norms: Vec<Norm>,
+    instrs: Vec<Instr>,
+}
+
+impl Compiler {
+    fn emit(&mut self, instr: Instr) -> usize {
+        let next = self.index.len() + self.instrs.len();
+        *self.seen.entry(instr).or_insert_with(|| {
+            self.instrs.push(instr);
+            next
+        })
+    }
+
+    fn norm(&mut self, norm: Norm) -> usize {
+        match self.norms.iter().position(|&n| n == norm) {
+            Some(i) => i,
+            None => {
+                self.norms.push(norm);
+                self.norms.len() - 1
+            }
+        }
+    }
+
+    fn child(&mut self, child: &Option<Box<Rule>>, norm: Norm) -> Result<usize, FuzzyError> {
+        let child = child
+            .as_ref()
+            .ok_or_else(|| FuzzyError::MalformedRule("Op at end of tree".to_string()))?;
+        self.node(child, norm)
+    }
+
+    /// Slot holding the value of `rule`, with `norm` for the ANDs and ORs without their own.
+    fn node(&mut self, rule: &Rule, norm: Norm) -> Result<usize, FuzzyError> {
+        let norm = rule.inner_norm(norm);
+        let instr = match &rule.val {
+            RuleNode::Input(t) => return Ok(self.index[t]),
+            RuleNode::Ordinal(_, terms) => {
+                let slots: Vec<usize> = terms.iter().map(|t| self.index[t]).collect();
+                let (&first, rest) = slots.split_first().ok_or_else(|| {
+                    FuzzyError::MalformedRule("ordinal condition without terms".to_string())
+                })?;
+                return Ok(rest.iter().fold(first, |acc, &s| {
+                    self.emit(Instr::Max(acc.min(s), acc.max(s)))
+                }));
+            }
+            RuleNode::Hedge(h) => Instr::Hedge(*h, self.child(&rule.left, norm)?),
+            RuleNode::Op(Op::Not) => Instr::Not(self.child(&rule.left, norm)?),
+            RuleNode::Op(op) => {
+                let (a, b) = (
+                    self.child(&rule.left, norm)?,
+                    self.child(&rule.right, norm)?,
+                );
+                let (a, b) = (a.min(b), a.max(b));
+                let n = self.norm(norm);
+                match op {
+                    Op::And(_) => Instr::And(n, a, b),
+                    _ => Instr::Or(n, a, b),
+                }
+            }
+        };
+        Ok(self.emit(instr))
+    }
+}
+
+impl Program {
+    /// Compiles weighted rules, with `norm` for any AND and OR without their own.
+    /// The terms get slots in the order the rules use them.
+    pub fn compile<'a>(
+        rules: impl IntoIterator<Item = (&'a Rule, f32)>,
+        norm: Norm,
+    ) -> Result<Program, FuzzyError> {
+        let rules: Vec<(&Rule, f32)> = rules.into_iter().collect();
+        let terms: Vec<Term> = rules.iter().flat_map(|(r, _)| r.inputs()).collect();
+        Program::with_terms(terms, rules, norm)
+    }
+
+    /// Same as [`compile`](Self::compile), with the terms in the slots given by
+    /// `terms`, which must include every term of the rules.
+    pub fn with_terms<'a>(
+        terms: impl IntoIterator<Item = Term>,
+        rules: impl IntoIterator<Item = (&'a Rule, f32)>,
+        norm: Norm,
+    ) -> Result<Program, FuzzyError> {
+        let mut index = HashMap::new();
+        let mut order = Vec::new();
+        for term in terms {
+            index.entry(term.clone()).or_insert_with(|| {
+                order.push(term);
+                order.len() - 1
+            });
+        }
+        let rules: Vec<(&Rule, f32)> = rules.into_iter().collect();
+        let mut used = vec![false; order.len()];
+        for t in rules.iter().flat_map(|(r, _)| r.inputs()) {
+            let i = index.get(&t).ok_or(FuzzyError::MissingMembership(t))?;
+            used[*i] = true;
+        }
+        let mut compiler = Compiler {
+            index,
+            seen: HashMap::new(),
+            norms: Vec::new(),
+            instrs: Vec::new(),
+        };
+        let slots = rules
+            .iter()
+            .map(|(r, w)| Ok((compiler.node(r, norm)?, *w)))
+            .collect::<Result<_, FuzzyError>>()?;
+        Ok(Program {
+            terms: order,
+            index: compiler.index,
+            seen: compiler.seen,
+            norm,
+            used,
+            norms: compiler.norms,
+            instrs: compiler.instrs,
+            rules: slots,
+        })
+    }
+
+    /// Input terms in the order of their slots.
+    pub fn terms(&self) -> &[Term] {
+        &self.terms
+    }
+
+    /// Number of instructions after deduplication.
+    pub fn instructions(&self) -> usize {
+        self.instrs.len()
+    }
+
+    /// Number of slots [`eval`](Self::eval) needs.
+    pub fn slots(&self) -> usize {
+        self.terms.len() + self.instrs.len()
+    }
+
+    /// Runs the instructions over `slots`, whose first slots must already hold
+    /// the memberships of the terms.
+    pub fn eval(&self, slots: &mut [f32]) {
+        self.eval_lanes(slots, 1, 1);
+    }
+
+    /// Same as [`eval`](Self::eval) for `n` evaluations side by side, slot `s`
+    /// of evaluation `r` being `slots[s * lanes + r]`. Each instruction runs over
+    /// all of them before the next.
+    pub fn eval_lanes(&self, slots: &mut [f32], lanes: usize, n: usize) {
+        let first = self.terms.len();
+        for (i, instr) in self.instrs.iter().enumerate() {
+            let (read, write) = slots.split_at_mut((first + i) * lanes);
+            let out = &mut write[..n];
+            let lane = |s: usize| &read[s * lanes..s * lanes + n];
+            match *instr {
+                Instr::Max(a, b) => {
+                    for (o, (x, y)) in out.iter_mut().zip(lane(a).iter().zip(lane(b))) {
+                        *o = x.max(*y);
+                    }
+                }
+                Instr::Hedge(h, a) => {
+                    for (o, x) in out.iter_mut().zip(lane(a)) {
+                        *o = h.apply(*x);
+                    }
+                }
+                Instr::Not(a) => {
+                    for (o, x) in out.iter_mut().zip(lane(a)) {
+                        *o = 1. - x;
+                    }
+                }
+                Instr::And(k, a, b) => {
+                    let norm = self.norms[k];
+                    for (o, (x, y)) in out.iter_mut().zip(lane(a).iter().zip(lane(b))) {
+                        *o = norm.and(*x, *y);
+                    }
+                }
+                Instr::Or(k, a, b) => {
+                    let norm = self.norms[k];
+                    for (o, (x, y)) in out.iter_mut().zip(lane(a).iter().zip(lane(b))) {
+                        *o = norm.or(*x, *y);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Weighted firing strength of every rule, from slots filled by [`eval`](Self::eval).
+    pub fn strengths<'a>(&'a self, slots: &'a [f32]) -> impl Iterator<Item = f32> + 'a {
+        self.lane_strengths(slots, 1, 0)
+    }
+
+    /// Same as [`strengths`](Self::strengths) for evaluation `lane` of [`eval_lanes`](Self::eval_lanes).
+    pub fn lane_strengths<'a>(
+        &'a self,
+        slots: &'a [f32],
+        lanes: usize,
+        lane: usize,
+    ) -> impl Iterator<Item = f32> + 'a {
+        self.rules
+            .iter()
+            .map(move |&(s, w)| w * slots[s * lanes + lane])
+    }
+
+    /// Value of every node of `rule`, one of the compiled rules, read from slots
+    /// filled by [`eval`](Self::eval) instead of computed again.
+    pub fn trace(&self, rule: &Rule, slots: &[f32]) -> Result<NodeTrace, FuzzyError> {
+        self.traced(rule, slots, self.norm).map(|(_, trace)| trace)
+    }
+
+    /// Slot and trace of `rule`, with `norm` for the ANDs and ORs without their own.
+    fn traced(
+        &self,
+        rule: &Rule,
+        slots: &[f32],
+        norm: Norm,
+    ) -> Result<(usize, NodeTrace), FuzzyError> {
+        let inner = rule.inner_norm(norm);
+        let not_compiled = || FuzzyError::MalformedRule(format!("{} was not compiled", rule));
+        let term = |t: &Term| self.index.get(t).copied().ok_or_else(not_compiled);
+        let instr = |instr| self.seen.get(&instr).copied().ok_or_else(not_compiled);
+        let child = |child: &Option<Box<Rule>>| {
+            let child = child
+                .as_ref()
+                .ok_or_else(|| FuzzyError::MalformedRule("Op at end of tree".to_string()))?;
+            self.traced(child, slots, inner)
+        };
+        let (slot, children) = match &rule.val {
+            RuleNode::Input(t) => (term(t)?, Vec::new()),
+            RuleNode::Ordinal(_, terms) => {
+                let mut slots = terms.iter().map(term);
+                let first = slots.next().ok_or_else(not_compiled)??;
+                let slot = slots.try_fold(first, |acc, s| {
+                    let s = s?;
+                    instr(Instr::Max(acc.min(s), acc.max(s)))
+                })?;
+                (slot, Vec::new())
+            }
+            RuleNode::Hedge(h) => {
+                let (a, trace) = child(&rule.left)?;
+                (instr(Instr::Hedge(*h, a))?, vec![trace])
+            }
+            RuleNode::Op(Op::Not) => {
+                let (a, trace) = child(&rule.left)?;
+                (instr(Instr::Not(a))?, vec![trace])
+            }
+            RuleNode::Op(op) => {
+                let ((a, left), (b, right)) = (child(&rule.left)?, child(&rule.right)?);
+                let (a, b) = (a.min(b), a.max(b));
+                let n = self
+                    .norms
+                    .iter()
+                    .position(|&m| m == inner)
+                    .ok_or_else(not_compiled)?;
+                let slot = match op {
+                    Op::And(_) => instr(Instr::And(n, a, b))?,
+                    _ => instr(Instr::Or(n, a, b))?,
+                };
+                (slot, vec![left, right])
+            }
+        };
+        let node = match &rule.val {
+            RuleNode::Op(Op::And(None)) => RuleNode::Op(Op::And(Some(norm))),
+            RuleNode::Op(Op::Or(None)) => RuleNode::Op(Op::Or(Some(norm))),
+            val => val.clone(),
+        };
+        let trace = NodeTrace {
+            node,
+            value: slots[slot],
+            children,
+        };
+        Ok((slot, trace))
+    }
+
+    /// Firing strength of every rule, in the order they were compiled.
+    pub fn fire(&self, finputs: &HashMap<Term, f32>) -> Result<Vec<f32>, FuzzyError> {
+        let mut slots = vec![0.; self.slots()];
+        for ((slot, term), used) in slots.iter_mut().zip(&self.terms).zip(&self.used) {
+            *slot = match finputs.get(term) {
+                Some(&mu) => mu,
+                None if *used => return Err(FuzzyError::MissingInput(term.var().clone())),
+                None => 0.,
+            };
+        }
+        self.eval(&mut slots);
+        Ok(self.strengths(&slots).collect())
+    }
+}
+
+/// A column of crisp values with its variable and the variable's first slot.
+pub(crate) type Column<'a> = (&'a Var, usize, &'a [f32]);
+
+/// Where the terms of a set of input variables sit in a [`Program`] over all of them.
+///
+/// The terms of each variable take consecutive slots, so fuzzifying a variable
+/// writes straight into them.
+#[derive(Debug, Clone)]
+pub(crate) struct Slots {
+    /// Index and first slot of each variable.
+    vars: HashMap<Var, (usize, usize)>,
+    /// Whether a rule uses the variable.
+    needed: Vec<bool>,
+}
+
+impl Slots {
+    pub fn compile<'a>(
+        rules: impl IntoIterator<Item = (&'a Rule, f32)>,
+        inputs: &HashMap<Var, Fuzzy<Term>>,
+        norm: Norm,
+    ) -> Result<(Program, Slots), FuzzyError> {
+        let rules: Vec<(&Rule, f32)> = rules.into_iter().collect();
+        let mut names: Vec<&Var> = inputs.keys().collect();
+        names.sort_by(|a, b| a.name().cmp(b.name()));
+        let mut vars = HashMap::new();
+        let mut terms = Vec::new();
+        for (i, &var) in names.iter().enumerate() {
+            vars.insert(var.clone(), (i, terms.len()));
+            terms.extend(inputs[var].terms().iter().cloned());
+        }
+        let program = Program::with_terms(terms, rules.iter().copied(), norm)?;
+        let mut needed = vec![false; names.len()];
+        for term in rules.iter().flat_map(|(r, _)| r.inputs()) {
+            needed[vars[term.var()].0] = true;
+        }
+        Ok((program, Slots { vars, needed }))
+    }
+
+    /// Pairs each column of crisp values with the first slot of its variable,
+    /// checking that every column has `rows` values and none that a rule needs is missing.
+    pub fn columns<'a>(
+        &self,
+        inputs: &'a [(Var, &'a [f32])],
+        rows: usize,
+    ) -> Result<Vec<Column<'a>>, FuzzyError> {
+        let mut given = vec![false; self.needed.len()];
+        let mut columns = Vec::with_capacity(inputs.len());
+        for (var, column) in inputs {
+            let &(i, first) = self
+                .vars
+                .get(var)
+                .ok_or_else(|| FuzzyError::UnknownInput(var.clone()))?;
+            if column.len() != rows {
+                return Err(FuzzyError::ColumnLength(var.clone(), column.len(), rows));
+            }
+            given[i] = true;
+            columns.push((var, first, *column));
+        }
+        let missing = self
+            .vars
+            .iter()
+            .find(|(_, &(i, _))| self.needed[i] && !given[i]);
+        if let Some((var, _)) = missing {
+            return Err(FuzzyError::MissingInput(var.clone()));
+        }
+        Ok(columns)
+    }
+
+    /// Fuzzifies the crisp inputs into the slots of `snapshot`, recording them
+    /// there, and runs `program`.
+    pub fn fill(
+        &self,
+        inputs: &HashMap<Var, Fuzzy<Term>>,
+        crisp: &[(Var, f32)],
+        program: &Program,
+        snapshot: &mut Snapshot,
+    ) -> Result<(), FuzzyError> {
+        snapshot.given.fill(false);
+        for (var, x) in crisp {
+            let (&(i, first), fuzzy) = self
+                .vars
+                .get(var)
+                .zip(inputs.get(var))
+                .ok_or_else(|| FuzzyError::UnknownInput(var.clone()))?;
+            snapshot.input_at(var, *x, fuzzy.range());
+            fuzzy.memberships(*x, &mut snapshot.values[first..])?;
+            snapshot.given[i] = true;
+        }
+        let missing = self
+            .vars
+            .iter()
+            .find(|(_, &(i, _))| self.needed[i] && !snapshot.given[i]);
+        if let Some((var, _)) = missing {
+            return Err(FuzzyError::MissingInput(var.clone()));
+        }
+        program.eval(&mut snapshot.values);
+        Ok(())
+    }
+
+    /// First slot of the terms of `var`.
+    pub fn first(&self, var: &Var) -> Option<usize> {
+        self.vars.get(var).map(|&(_, first)| first)
+    }
+
+    /// Number of input variables.
+    pub fn vars(&self) -> usize {
+        self.needed.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_rules, variables::Registry};
+
+    const RULES: &str = "
+        IF Vx IS P AND Th IS N AND X IS N THEN Diff IS PM
+        IF Th IS N AND Vx IS P THEN Diff IS PS USING PRODUCT
+        IF X IS AT LEAST Z OR NOT Th IS VERY N THEN Diff IS NS WITH 0.5
+        IF SOMEWHAT (Vx IS P AND Th IS N) THEN Diff IS Z
+    ";
+
+    #[test]
+    fn matches_the_rule_trees() {
+        let rules = parse_rules(RULES, &Registry::typed()).unwrap();
+        let program =
+            Program::compile(rules.iter().map(|(_, r, w)| (r, *w)), Norm::Einstein).unwrap();
+        let mut finputs = HashMap::new();
+        for (i, term) in program.terms().iter().enumerate() {
+            finputs.insert(term.clone(), (i as f32 * 0.37).fract());
+        }
+        let strengths = program.fire(&finputs).unwrap();
+        for ((_, rule, w), s) in rules.iter().zip(strengths) {
+            let expected = w * rule.resolve(&finputs, Norm::Einstein).unwrap();
+            assert!((s - expected).abs() < 1e-6, "{} gave {}", rule, s);
+        }
+    }
+
+    #[test]
+    fn shares_sub_expressions() {
+        let rules = parse_rules(RULES, &Registry::typed()).unwrap();
+        let program = Program::compile(rules.iter().map(|(_, r, w)| (r, *w)), Norm::Zadeh).unwrap();
+        // Vx AND Th, shared by the first and last rules, its AND X, its PRODUCT
+        // twin, the max of Z and P, VERY, NOT, the OR and SOMEWHAT
+        assert_eq!(program.instructions(), 8);
+    }
+}
@@ -1,4 +1,10 @@
+use crate::{
+    error::FuzzyError,
+    trace::NodeTrace,
+    variables::{Registry, Term, Var},
+};
 use std::{
+    collections::HashMap,
     fmt::Display,
     ops::{BitAnd, BitOr, Not},
 };
@@ -78,6 +84,174 @@ pub enum W {
     P,
 }
 
+impl InputType {
+    pub const ALL: [InputType; 6] = [
+        InputType::X,
+        InputType::Y,
+        InputType::W,
+        InputType::Vy,
+        InputType::Vx,
+        InputType::Th,
+    ];
+}
+
+impl Inputs {
+    pub const ALL: [Inputs; 14] = [
+        Inputs::Y(Y::N),
+        Inputs::Y(Y::P),
+        Inputs::Vy(Vy::N),
+        Inputs::Vy(Vy::P),
+        Inputs::Vx(Vx::N),
+        Inputs::Vx(Vx::P),
+        Inputs::X(X::N),
+        Inputs::X(X::Z),
+        Inputs::X(X::P),
+        Inputs::Th(Th::N),
+        Inputs::Th(Th::Z),
+        Inputs::Th(Th::P),
+        Inputs::W(W::N),
+        Inputs::W(W::P),
+    ];
+
+    /// Name of the term within its variable, as used in text rules.
+    pub fn term(&self) -> &'static str {
+        match self {
+            Inputs::Y(Y::N)
+            | Inputs::Vy(Vy::N)
+            | Inputs::Vx(Vx::N)
+            | Inputs::X(X::N)
+            | Inputs::Th(Th::N)
+            | Inputs::W(W::N) => "N",
+            Inputs::X(X::Z) | Inputs::Th(Th::Z) => "Z",
+            Inputs::Y(Y::P)
+            | Inputs::Vy(Vy::P)
+            | Inputs::Vx(Vx::P)
+            | Inputs::X(X::P)
+            | Inputs::Th(Th::P)
+            | Inputs::W(W::P) => "P",
+        }
+    }
+
+    pub fn input_type(&self) -> InputType {
+        match self {
+            Inputs::Y(_) => InputType::Y,
+            Inputs::Vy(_) => InputType::Vy,
+            Inputs::Vx(_) => InputType::Vx,
+            Inputs::X(_) => InputType::X,
+            Inputs::Th(_) => InputType::Th,
+            Inputs::W(_) => InputType::W,
+        }
+    }
+}
+
+impl Outputs {
+    pub const ALL: [Outputs; 10] = [
+        Outputs::Amp(Amp::Z),
+        Outputs::Amp(Amp::S),
+        Outputs::Amp(Amp::L),
+        Outputs::Diff(Diff::NL),
+        Outputs::Diff(Diff::NM),
+        Outputs::Diff(Diff::NS),
+        Outputs::Diff(Diff::Z),
+        Outputs::Diff(Diff::PS),
+        Outputs::Diff(Diff::PM),
+        Outputs::Diff(Diff::PL),
+    ];
+
+    /// Name of the output variable, as used in text rules.
+    pub fn variable(&self) -> &'static str {
+        match self {
+            Outputs::Amp(_) => "Amp",
+            Outputs::Diff(_) => "Diff",
+        }
+    }
+}
+
+impl From<InputType> for Var {
+    fn from(i: InputType) -> Self {
+        Var::new(&i.to_string())
+    }
+}
+
+impl From<Inputs> for Term {
+    fn from(i: Inputs) -> Self {
+        Term::new(i.input_type(), i.term())
+    }
+}
+
+impl From<Outputs> for Term {
+    fn from(o: Outputs) -> Self {
+        Term::new(o.variable(), &o.to_string())
+    }
+}
+
+/// Membership in the best matching of `terms`.
+///
+/// This is a max whatever the norm of the rule: the terms are alternatives for
+/// one value of one variable, so `X IS AT LEAST Z` should hold exactly as much
+/// as the better of `Z` and `P`. A probabilistic or bounded sum would add up
+/// where neighbouring terms overlap, and the same condition would mean
+/// something else under each norm.
+fn any(terms: &[Term], finputs: &HashMap<Term, f32>) -> Result<f32, FuzzyError> {
+    terms.iter().try_fold(0f32, |acc, t| {
+        let mu = finputs
+            .get(t)
+            .ok_or_else(|| FuzzyError::MissingInput(t.var().clone()))?;
+        Ok(acc.max(*mu))
+    })
+}
+
+impl Registry {
+    /// `term` or any term ordered after it.
+    pub fn at_least(&self, term: impl Into<Term>) -> Option<Rule> {
+        self.ordinal(Ordinal::AtLeast(term.into()))
+    }
+
+    /// `term` or any term ordered before it.
+    pub fn at_most(&self, term: impl Into<Term>) -> Option<Rule> {
+        self.ordinal(Ordinal::AtMost(term.into()))
+    }
+
+    /// Any term from `low` to `high`, or `None` if `high` comes before `low`.
+    pub fn between(&self, low: impl Into<Term>, high: impl Into<Term>) -> Option<Rule> {
+        self.ordinal(Ordinal::Between(low.into(), high.into()))
+    }
+
+    /// The condition with the terms it covers, in the order of the registry.
+    pub fn ordinal(&self, ordinal: Ordinal) -> Option<Rule> {
+        let variable = self.get(ordinal.var().name())?;
+        let last = variable.terms.len().checked_sub(1)?;
+        let (low, high) = match &ordinal {
+            Ordinal::AtLeast(t) => (variable.position(t)?, last),
+            Ordinal::AtMost(t) => (0, variable.position(t)?),
+            Ordinal::Between(a, b) => (variable.position(a)?, variable.position(b)?),
+        };
+        if low > high {
+            return None;
+        }
+        let terms = variable.terms[low..=high].to_vec();
+        Some(Rule::new(RuleNode::Ordinal(ordinal, terms), None, None))
+    }
+
+    /// Every variable of the enums above, with their terms.
+    pub fn typed() -> Self {
+        let mut registry = Registry::new();
+        for i in InputType::ALL {
+            let terms = Inputs::ALL.into_iter().filter(|t| t.input_type() == i);
+            registry
+                .define(i, terms.map(|t| t.term()))
+                .expect("input types are distinct");
+        }
+        for var in ["Amp", "Diff"] {
+            let terms = Outputs::ALL.into_iter().filter(|o| o.variable() == var);
+            registry
+                .define(var, terms.map(|t| t.to_string()))
+                .expect("output types are distinct");
+        }
+        registry
+    }
+}
+
 impl Display for X {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -187,16 +361,255 @@ impl Display for InputType {
     }
 }
 
-#[derive(Debug, Clone)]
+/// A family of connectives: a t-norm for AND and its dual s-norm for OR.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Norm {
+    /// `min(a, b)` and `max(a, b)`.
+    Zadeh,
+    /// Algebraic product `ab` and probabilistic sum `a + b - ab`.
+    Product,
+    /// `max(0, a + b - 1)` and `min(1, a + b)`.
+    Lukasiewicz,
+    /// Min when either side is 1 and 0 otherwise; max when either side is 0 and 1 otherwise.
+    Drastic,
+    /// Min when `a + b > 1` and 0 otherwise; max when `a + b < 1` and 1 otherwise.
+    Nilpotent,
+    /// `ab / (2 - (a + b - ab))` and `(a + b) / (1 + ab)`.
+    Einstein,
+    /// `ab / (g + (1 - g)(a + b - ab))` for `g >= 0`, with its dual.
+    /// 0 gives the Hamacher product, 1 the algebraic product and 2 Einstein.
+    Hamacher(f32),
+    /// `max(0, 1 - ((1 - a)^p + (1 - b)^p)^(1/p))` and `min(1, (a^p + b^p)^(1/p))` for `p > 0`.
+    Yager(f32),
+}
+
+impl Norm {
+    pub const ALL: [Norm; 8] = [
+        Norm::Zadeh,
+        Norm::Product,
+        Norm::Lukasiewicz,
+        Norm::Drastic,
+        Norm::Nilpotent,
+        Norm::Einstein,
+        Norm::Hamacher(0.),
+        Norm::Yager(2.),
+    ];
+
+    /// The t-norm.
+    pub fn and(&self, a: f32, b: f32) -> f32 {
+        match *self {
+            Norm::Zadeh => a.min(b),
+            Norm::Product => a * b,
+            Norm::Lukasiewicz => (a + b - 1.).max(0.),
+            Norm::Drastic if a == 1. || b == 1. => a.min(b),
+            Norm::Drastic => 0.,
+            Norm::Nilpotent if a + b > 1. => a.min(b),
+            Norm::Nilpotent => 0.,
+            Norm::Einstein => a * b / (2. - (a + b - a * b)),
+            Norm::Hamacher(g) => {
+                let den = g + (1. - g) * (a + b - a * b);
+                if den == 0. {
+                    0.
+                } else {
+                    a * b / den
+                }
+            }
+            Norm::Yager(p) => (1. - ((1. - a).powf(p) + (1. - b).powf(p)).powf(1. / p)).max(0.),
+        }
+    }
+
+    /// The s-norm, `1 - and(1 - a, 1 - b)`.
+    pub fn or(&self, a: f32, b: f32) -> f32 {
+        match *self {
+            Norm::Zadeh => a.max(b),
+            Norm::Product => a + b - a * b,
+            Norm::Lukasiewicz => (a + b).min(1.),
+            Norm::Drastic if a == 0. || b == 0. => a.max(b),
+            Norm::Drastic => 1.,
+            Norm::Nilpotent if a + b < 1. => a.max(b),
+            Norm::Nilpotent => 1.,
+            Norm::Einstein => (a + b) / (1. + a * b),
+            Norm::Hamacher(_) => 1. - self.and(1. - a, 1. - b),
+            Norm::Yager(p) => (a.powf(p) + b.powf(p)).powf(1. / p).min(1.),
+        }
+    }
+
+    /// Whether the parameter, if any, is in its domain.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Norm::Hamacher(g) => g.is_finite() && g >= 0.,
+            Norm::Yager(p) => p.is_finite() && p > 0.,
+            _ => true,
+        }
+    }
+
+    /// Word used in explanations; text rules take it in any case.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Norm::Zadeh => "zadeh",
+            Norm::Product => "product",
+            Norm::Lukasiewicz => "lukasiewicz",
+            Norm::Drastic => "drastic",
+            Norm::Nilpotent => "nilpotent",
+            Norm::Einstein => "einstein",
+            Norm::Hamacher(_) => "hamacher",
+            Norm::Yager(_) => "yager",
+        }
+    }
+}
+
+/// The keyword, followed by the parameter if there is one, e.g. `YAGER 2`.
+impl Display for Norm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name().to_uppercase())?;
+        match self {
+            Norm::Hamacher(x) | Norm::Yager(x) => write!(f, " {}", x),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A connective. AND and OR use their own [`Norm`] if they have one, and
+/// otherwise that of the nearest AND or OR above them with one, or the
+/// controller's. This is how text rules read a group marked `USING`.
+#[derive(Debug, PartialEq, Clone)]
 pub enum Op {
-    And(fn(f32, f32) -> f32),
-    Or(fn(f32, f32) -> f32),
-    Not(fn(f32) -> f32),
+    And(Option<Norm>),
+    Or(Option<Norm>),
+    /// `1 - a`
+    Not,
+}
+
+impl Op {
+    pub fn norm(&self) -> Option<Norm> {
+        match self {
+            Op::And(n) | Op::Or(n) => *n,
+            Op::Not => None,
+        }
+    }
+
+    /// Combines the values of the children, one for NOT and two otherwise.
+    pub fn apply(&self, a: f32, b: f32, default: Norm) -> f32 {
+        match self {
+            Op::And(n) => n.unwrap_or(default).and(a, b),
+            Op::Or(n) => n.unwrap_or(default).or(a, b),
+            Op::Not => 1. - a,
+        }
+    }
+}
+
+/// Sharpens or softens how strongly a condition holds.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Hedge {
+    /// Concentration, `mu^2`.
+    Very,
+    /// `mu^3`
+    Extremely,
+    /// Dilation, `mu^(1/2)`.
+    Somewhat,
+    /// `mu^(1/3)`
+    Slightly,
+    /// Contrast intensification: pushes values below one half down and above it up.
+    Indeed,
+}
+
+impl Hedge {
+    pub const ALL: [Hedge; 5] = [
+        Hedge::Very,
+        Hedge::Extremely,
+        Hedge::Somewhat,
+        Hedge::Slightly,
+        Hedge::Indeed,
+    ];
+
+    pub fn apply(&self, mu: f32) -> f32 {
+        match self {
+            Hedge::Very => mu * mu,
+            Hedge::Extremely => mu * mu * mu,
+            Hedge::Somewhat => mu.sqrt(),
+            Hedge::Slightly => mu.cbrt(),
+            Hedge::Indeed if mu <= 0.5 => 2. * mu * mu,
+            Hedge::Indeed => 1. - 2. * (1. - mu) * (1. - mu),
+        }
+    }
+
+    /// Word used in explanations; text rules take it in any case.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hedge::Very => "very",
+            Hedge::Extremely => "extremely",
+            Hedge::Somewhat => "somewhat",
+            Hedge::Slightly => "slightly",
+            Hedge::Indeed => "indeed",
+        }
+    }
+
+    pub fn of(self, rule: impl Into<Rule>) -> Rule {
+        Rule::new(RuleNode::Hedge(self), Some(rule.into()), None)
+    }
+}
+
+impl Display for Hedge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name().to_uppercase())
+    }
+}
+
+pub fn very(rule: impl Into<Rule>) -> Rule {
+    Hedge::Very.of(rule)
+}
+
+pub fn extremely(rule: impl Into<Rule>) -> Rule {
+    Hedge::Extremely.of(rule)
+}
+
+pub fn somewhat(rule: impl Into<Rule>) -> Rule {
+    Hedge::Somewhat.of(rule)
+}
+
+pub fn slightly(rule: impl Into<Rule>) -> Rule {
+    Hedge::Slightly.of(rule)
+}
+
+pub fn indeed(rule: impl Into<Rule>) -> Rule {
+    Hedge::Indeed.of(rule)
+}
+
+/// A condition on where the value of a variable lies among its ordered terms.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Ordinal {
+    AtLeast(Term),
+    AtMost(Term),
+    /// Both ends included.
+    Between(Term, Term),
+}
+
+impl Ordinal {
+    pub fn var(&self) -> &Var {
+        match self {
+            Ordinal::AtLeast(t) | Ordinal::AtMost(t) | Ordinal::Between(t, _) => t.var(),
+        }
+    }
+}
+
+impl Display for Ordinal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ordinal::AtLeast(t) => write!(f, "AT LEAST {}", t),
+            Ordinal::AtMost(t) => write!(f, "AT MOST {}", t),
+            Ordinal::Between(a, b) => write!(f, "BETWEEN {} AND {}", a, b),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum RuleNode {
-    Input(Inputs),
+    Input(Term),
+    /// Holds to the degree of its best matching term, the terms being every
+    /// term the condition covers. The best is taken with max, not the rule's OR norm.
+    Ordinal(Ordinal, Vec<Term>),
+    /// Applies to its only (left) child.
+    Hedge(Hedge),
     Op(Op),
 }
 
@@ -215,91 +628,351 @@ impl Rule {
             right: right.map(Box::new),
         }
     }
+
+    /// Checks that every op node has the children it needs and a valid norm.
+    pub fn validate(&self) -> Result<(), FuzzyError> {
+        if let RuleNode::Op(o) = &self.val {
+            if let Some(n) = o.norm().filter(|n| !n.is_valid()) {
+                return Err(FuzzyError::InvalidNorm(n));
+            }
+        }
+        let arity = match &self.val {
+            RuleNode::Input(_) | RuleNode::Ordinal(..) => 0,
+            RuleNode::Hedge(_) | RuleNode::Op(Op::Not) => 1,
+            RuleNode::Op(_) => 2,
+        };
+        let children = [&self.left, &self.right];
+        for (n, child) in children.iter().enumerate() {
+            match (child, n < arity) {
+                (Some(c), true) => c.validate()?,
+                (None, false) => {}
+                (Some(_), false) => {
+                    return Err(FuzzyError::MalformedRule(format!(
+                        "{:?} has an extra child",
+                        self.val
+                    )))
+                }
+                (None, true) => {
+                    return Err(FuzzyError::MalformedRule(format!(
+                        "{:?} is missing a child",
+                        self.val
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// All input terms used by the rule, left to right.
+    pub fn inputs(&self) -> Vec<Term> {
+        match &self.val {
+            RuleNode::Input(i) => vec![i.clone()],
+            RuleNode::Ordinal(_, terms) => terms.clone(),
+            RuleNode::Hedge(_) | RuleNode::Op(_) => [&self.left, &self.right]
+                .into_iter()
+                .flatten()
+                .flat_map(|r| r.inputs())
+                .collect(),
+        }
+    }
+
+    /// Truth value of the rule, with `norm` for any AND and OR that neither
+    /// have nor inherit their own.
+    pub fn resolve(&self, finputs: &HashMap<Term, f32>, norm: Norm) -> Result<f32, FuzzyError> {
+        let child = |c: &Option<Box<Rule>>| {
+            c.as_ref()
+                .ok_or_else(|| FuzzyError::MalformedRule("Op at end of tree".to_string()))?
+                .resolve(finputs, self.inner_norm(norm))
+        };
+        match &self.val {
+            RuleNode::Input(i) => finputs
+                .get(i)
+                .copied()
+                .ok_or_else(|| FuzzyError::MissingInput(i.var().clone())),
+            RuleNode::Ordinal(_, terms) => any(terms, finputs),
+            RuleNode::Hedge(h) => Ok(h.apply(child(&self.left)?)),
+            RuleNode::Op(o) => {
+                let left = child(&self.left)?;
+                if let Op::Not = o {
+                    if self.right.is_some() {
+                        return Err(FuzzyError::MalformedRule(
+                            "Not op must have only one (left) child".to_string(),
+                        ));
+                    }
+                    return Ok(o.apply(left, 0., norm));
+                }
+                Ok(o.apply(left, child(&self.right)?, norm))
+            }
+        }
+    }
+
+    /// Same as [`resolve`](Self::resolve), keeping the value of every sub-expression.
+    /// Every AND and OR in the trace names the norm it was evaluated with.
+    pub fn trace(&self, finputs: &HashMap<Term, f32>, norm: Norm) -> Result<NodeTrace, FuzzyError> {
+        let children = match &self.val {
+            RuleNode::Input(_) | RuleNode::Ordinal(..) => Vec::new(),
+            RuleNode::Hedge(_) | RuleNode::Op(_) => [&self.left, &self.right]
+                .into_iter()
+                .flatten()
+                .map(|c| c.trace(finputs, self.inner_norm(norm)))
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        let value = match (&self.val, children.as_slice()) {
+            (RuleNode::Input(i), []) => finputs
+                .get(i)
+                .copied()
+                .ok_or_else(|| FuzzyError::MissingInput(i.var().clone()))?,
+            (RuleNode::Ordinal(_, terms), []) => any(terms, finputs)?,
+            (RuleNode::Hedge(h), [a]) => h.apply(a.value),
+            (RuleNode::Op(o @ Op::Not), [a]) => o.apply(a.value, 0., norm),
+            (RuleNode::Op(o @ (Op::And(_) | Op::Or(_))), [a, b]) => o.apply(a.value, b.value, norm),
+            (val, _) => {
+                return Err(FuzzyError::MalformedRule(format!(
+                    "{:?} has the wrong number of children",
+                    val
+                )))
+            }
+        };
+        let node = match &self.val {
+            RuleNode::Op(Op::And(None)) => RuleNode::Op(Op::And(Some(norm))),
+            RuleNode::Op(Op::Or(None)) => RuleNode::Op(Op::Or(Some(norm))),
+            val => val.clone(),
+        };
+        Ok(NodeTrace {
+            node,
+            value,
+            children,
+        })
+    }
+
+    /// Gives every AND and OR without a norm of its own `norm`.
+    pub fn using(mut self, norm: Norm) -> Rule {
+        self.fill_norm(norm);
+        self
+    }
+
+    fn fill_norm(&mut self, norm: Norm) {
+        let norm = match &mut self.val {
+            RuleNode::Op(Op::And(n) | Op::Or(n)) => *n.get_or_insert(norm),
+            _ => norm,
+        };
+        for c in [&mut self.left, &mut self.right].into_iter().flatten() {
+            c.fill_norm(norm);
+        }
+    }
+
+    /// Norm that the ANDs and ORs under the node inherit, `norm` being the one
+    /// the node inherits itself.
+    pub(crate) fn inner_norm(&self, norm: Norm) -> Norm {
+        match &self.val {
+            RuleNode::Op(o) => o.norm().unwrap_or(norm),
+            _ => norm,
+        }
+    }
+
+    /// Norm of the outermost AND or OR, if it has one.
+    pub fn norm(&self) -> Option<Norm> {
+        match &self.val {
+            RuleNode::Input(_) | RuleNode::Ordinal(..) => None,
+            RuleNode::Hedge(_) | RuleNode::Op(Op::Not) => self.left.as_ref()?.norm(),
+            RuleNode::Op(o) => o.norm(),
+        }
+    }
+
+    /// Prints the rule as it would read under `USING norm`.
+    pub(crate) fn text(&self, norm: Option<Norm>) -> impl Display + '_ {
+        Using(self, norm)
+    }
+
+    /// The hedges above a leaf, outermost first, if there is nothing else in between.
+    fn hedged_leaf(&self) -> Option<(Vec<Hedge>, &Rule)> {
+        match &self.val {
+            RuleNode::Input(_) | RuleNode::Ordinal(..) => Some((Vec::new(), self)),
+            RuleNode::Hedge(h) => {
+                let (mut hedges, leaf) = self.left.as_ref()?.hedged_leaf()?;
+                hedges.insert(0, *h);
+                Some((hedges, leaf))
+            }
+            RuleNode::Op(_) => None,
+        }
+    }
+
+    /// Whether the node is an AND or OR with a norm other than `context`,
+    /// which is printed as a parenthesised group with its own `USING`.
+    fn own_norm(&self, context: Option<Norm>) -> Option<Norm> {
+        match &self.val {
+            RuleNode::Op(o) => o.norm().filter(|&n| Some(n) != context),
+            _ => None,
+        }
+    }
+
+    /// Binding strength of the node in text rules: OR < AND < NOT, hedges and leaves.
+    fn precedence(&self, context: Option<Norm>) -> u8 {
+        if self.own_norm(context).is_some() {
+            return 3;
+        }
+        match self.val {
+            RuleNode::Op(Op::Or(_)) => 1,
+            RuleNode::Op(Op::And(_)) => 2,
+            _ => 3,
+        }
+    }
+
+    fn fmt_leaf(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        negated: bool,
+        hedges: &[Hedge],
+    ) -> std::fmt::Result {
+        let mut words = if negated {
+            "NOT ".to_string()
+        } else {
+            String::new()
+        };
+        for h in hedges {
+            words += &format!("{} ", h);
+        }
+        match &self.val {
+            RuleNode::Input(i) => write!(f, "{} IS {}{}", i.var(), words, i),
+            RuleNode::Ordinal(o, _) => write!(f, "{} IS {}{}", o.var(), words, o),
+            _ => unreachable!("not a leaf"),
+        }
+    }
+
+    fn fmt_child(
+        f: &mut std::fmt::Formatter<'_>,
+        child: &Option<Box<Rule>>,
+        context: Option<Norm>,
+        parens: impl Fn(u8) -> bool,
+    ) -> std::fmt::Result {
+        match child {
+            Some(c) if parens(c.precedence(context)) => write!(f, "({})", c.text(context)),
+            Some(c) => write!(f, "{}", c.text(context)),
+            None => write!(f, "?"),
+        }
+    }
+
+    /// Prints the rule with the ANDs and ORs using `context` unmarked.
+    fn fmt_in(&self, f: &mut std::fmt::Formatter<'_>, context: Option<Norm>) -> std::fmt::Result {
+        if let Some(n) = self.own_norm(context) {
+            return write!(f, "({}) USING {}", self.text(Some(n)), n);
+        }
+        let p = self.precedence(context);
+        if let Some((hedges, leaf)) = self.hedged_leaf() {
+            return leaf.fmt_leaf(f, false, &hedges);
+        }
+        match &self.val {
+            RuleNode::Input(_) | RuleNode::Ordinal(..) => unreachable!("leaves are handled above"),
+            RuleNode::Hedge(h) => {
+                write!(f, "{} ", h)?;
+                Rule::fmt_child(f, &self.left, context, |c| c < p)
+            }
+            RuleNode::Op(Op::Not) => match self.left.as_ref().and_then(|l| l.hedged_leaf()) {
+                Some((hedges, leaf)) => leaf.fmt_leaf(f, true, &hedges),
+                None => {
+                    write!(f, "NOT ")?;
+                    Rule::fmt_child(f, &self.left, context, |c| c < p)
+                }
+            },
+            RuleNode::Op(op) => {
+                Rule::fmt_child(f, &self.left, context, |c| c < p)?;
+                write!(f, " {} ", if let Op::And(_) = op { "AND" } else { "OR" })?;
+                Rule::fmt_child(f, &self.right, context, |c| c <= p)
+            }
+        }
+    }
+}
+
+struct Using<'a>(&'a Rule, Option<Norm>);
+
+impl Display for Using<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_in(f, self.1)
+    }
+}
+
+/// Prints the rule in the syntax read by [`parse_rule`](crate::parser::parse_rule),
+/// with just enough parentheses to rebuild the same tree. ANDs and ORs with a
+/// norm of their own are grouped and marked `USING`.
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_in(f, None)
+    }
+}
+
+impl From<Term> for Rule {
+    fn from(val: Term) -> Self {
+        Rule::new(RuleNode::Input(val), None, None)
+    }
 }
 
 impl From<Inputs> for Rule {
     fn from(val: Inputs) -> Self {
-        Rule::new(RuleNode::Input(val), None, None)
+        Term::from(val).into()
     }
 }
 
-impl BitAnd for Inputs {
+impl<R: Into<Rule>> BitAnd<R> for Rule {
     type Output = Rule;
 
-    fn bitand(self, rhs: Inputs) -> Self::Output {
-        Rule::new(
-            RuleNode::Op(Op::And(f32::min)),
-            Some(self.into()),
-            Some(rhs.into()),
-        )
+    fn bitand(self, rhs: R) -> Self::Output {
+        Rule::new(RuleNode::Op(Op::And(None)), Some(self), Some(rhs.into()))
     }
 }
 
-impl BitAnd<Rule> for Inputs {
+impl<R: Into<Rule>> BitOr<R> for Rule {
     type Output = Rule;
 
-    fn bitand(self, rhs: Rule) -> Self::Output {
-        Rule::new(
-            RuleNode::Op(Op::And(f32::min)),
-            Some(self.into()),
-            Some(rhs),
-        )
+    fn bitor(self, rhs: R) -> Self::Output {
+        Rule::new(RuleNode::Op(Op::Or(None)), Some(self), Some(rhs.into()))
     }
 }
 
-impl BitAnd<Inputs> for Rule {
+impl Not for Rule {
     type Output = Rule;
 
-    fn bitand(self, rhs: Inputs) -> Self::Output {
-        Rule::new(
-            RuleNode::Op(Op::And(f32::min)),
-            Some(self),
-            Some(rhs.into()),
-        )
+    fn not(self: Rule) -> Self::Output {
+        Rule::new(RuleNode::Op(Op::Not), Some(self), None)
     }
 }
 
-impl BitAnd<Rule> for Rule {
+impl<R: Into<Rule>> BitAnd<R> for Term {
     type Output = Rule;
 
-    fn bitand(self, rhs: Rule) -> Self::Output {
-        Rule::new(RuleNode::Op(Op::And(f32::min)), Some(self), Some(rhs))
+    fn bitand(self, rhs: R) -> Self::Output {
+        Rule::from(self) & rhs
     }
 }
 
-impl BitOr for Inputs {
+impl<R: Into<Rule>> BitOr<R> for Term {
     type Output = Rule;
 
-    fn bitor(self, rhs: Inputs) -> Self::Output {
-        Rule::new(
-            RuleNode::Op(Op::Or(f32::max)),
-            Some(self.into()),
-            Some(rhs.into()),
-        )
+    fn bitor(self, rhs: R) -> Self::Output {
+        Rule::from(self) | rhs
     }
 }
 
-impl BitOr<Rule> for Inputs {
+impl Not for Term {
     type Output = Rule;
 
-    fn bitor(self, rhs: Rule) -> Self::Output {
-        Rule::new(RuleNode::Op(Op::Or(f32::max)), Some(self.into()), Some(rhs))
+    fn not(self) -> Self::Output {
+        !Rule::from(self)
     }
 }
 
-impl BitOr<Inputs> for Rule {
+impl<R: Into<Rule>> BitAnd<R> for Inputs {
     type Output = Rule;
 
-    fn bitor(self, rhs: Inputs) -> Self::Output {
-        Rule::new(RuleNode::Op(Op::Or(f32::max)), Some(self), Some(rhs.into()))
+    fn bitand(self, rhs: R) -> Self::Output {
+        Rule::from(self) & rhs
     }
 }
 
-impl BitOr<Rule> for Rule {
+impl<R: Into<Rule>> BitOr<R> for Inputs {
     type Output = Rule;
 
-    fn bitor(self, rhs: Rule) -> Self::Output {
-        Rule::new(RuleNode::Op(Op::Or(f32::max)), Some(self), Some(rhs))
+    fn bitor(self, rhs: R) -> Self::Output {
+        Rule::from(self) | rhs
     }
 }
 
@@ -307,14 +980,139 @@ impl Not for Inputs {
     type Output = Rule;
 
     fn not(self) -> Self::Output {
-        Rule::new(RuleNode::Op(Op::Not(|x| 1. - x)), Some(self.into()), None)
+        !Rule::from(self)
     }
 }
 
-impl Not for Rule {
-    type Output = Rule;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fuzzy::Fuzzy,
+        parser::parse_rule,
+        partition::{Partition, Shape},
+        program::Program,
+    };
 
-    fn not(self: Rule) -> Self::Output {
-        Rule::new(RuleNode::Op(Op::Not(|x| 1. - x)), Some(self), None)
+    const GRID: [f32; 6] = [0., 0.2, 0.4, 0.5, 0.8, 1.];
+
+    #[test]
+    fn norms_have_their_identities() {
+        for norm in Norm::ALL
+            .into_iter()
+            .chain([Norm::Hamacher(3.), Norm::Yager(0.5)])
+        {
+            for a in GRID {
+                assert!((norm.and(a, 1.) - a).abs() < 1e-5, "{} and({}, 1)", norm, a);
+                assert!((norm.or(a, 0.) - a).abs() < 1e-5, "{} or({}, 0)", norm, a);
+                for b in GRID {
+                    assert!(norm.and(a, b) <= a.min(b) + 1e-5, "{} above min", norm);
+                    assert!(norm.or(a, b) >= a.max(b) - 1e-5, "{} below max", norm);
+                    assert_eq!(norm.and(a, b), norm.and(b, a));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hamacher_spans_product_and_einstein() {
+        for a in GRID {
+            for b in GRID {
+                let h = |g| Norm::Hamacher(g).and(a, b);
+                assert!((h(1.) - Norm::Product.and(a, b)).abs() < 1e-5);
+                assert!((h(2.) - Norm::Einstein.and(a, b)).abs() < 1e-5);
+                assert!((Norm::Hamacher(2.).or(a, b) - Norm::Einstein.or(a, b)).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn ordinals_take_their_best_term() {
+        let mut registry = Registry::new();
+        let x = registry.define("X", ["N", "Z", "P"]).unwrap();
+        let finputs = HashMap::from([(x.term("N"), 0.2), (x.term("Z"), 0.7), (x.term("P"), 0.1)]);
+        for (rule, terms, value) in [
+            (registry.at_least(x.term("Z")), vec!["Z", "P"], 0.7),
+            (registry.at_least(x.term("P")), vec!["P"], 0.1),
+            (registry.at_most(x.term("Z")), vec!["N", "Z"], 0.7),
+            (registry.at_most(x.term("N")), vec!["N"], 0.2),
+            (
+                registry.between(x.term("N"), x.term("Z")),
+                vec!["N", "Z"],
+                0.7,
+            ),
+            (registry.between(x.term("P"), x.term("P")), vec!["P"], 0.1),
+        ] {
+            let rule = rule.unwrap();
+            let inputs: Vec<Term> = terms.into_iter().map(|t| x.term(t)).collect();
+            assert_eq!(rule.inputs(), inputs, "{}", rule);
+            for norm in [Norm::Zadeh, Norm::Product, Norm::Lukasiewicz] {
+                assert_eq!(
+                    rule.resolve(&finputs, norm).unwrap(),
+                    value,
+                    "{} {}",
+                    rule,
+                    norm
+                );
+                let program = Program::compile([(&rule, 1.)], norm).unwrap();
+                assert_eq!(
+                    program.fire(&finputs).unwrap(),
+                    [value],
+                    "{} {}",
+                    rule,
+                    norm
+                );
+            }
+        }
+        assert!(registry.between(x.term("Z"), x.term("N")).is_none());
+        assert!(registry.at_least(Var::new("Y").term("N")).is_none());
+    }
+
+    #[test]
+    fn hedges_reshape_memberships() {
+        let x = Var::new("X");
+        // N falls from 1 at 0 to 0 at 5
+        let partition = Partition::even(Shape::Tri, &(0.0..10.), 3).unwrap();
+        let fuzzy = Fuzzy::new(&x, partition.terms(x.clone()), 0.0..10.);
+        let mut registry = Registry::new();
+        registry.define(x.clone(), partition.names()).unwrap();
+        registry.define("Out", ["A"]).unwrap();
+        for (hedge, dsl, values) in [
+            (Hedge::Very, very(x.term("N")), [0.64, 0.25, 0.04]),
+            (
+                Hedge::Extremely,
+                extremely(x.term("N")),
+                [0.512, 0.125, 0.008],
+            ),
+            (
+                Hedge::Somewhat,
+                somewhat(x.term("N")),
+                [0.894_427, std::f32::consts::FRAC_1_SQRT_2, 0.447_214],
+            ),
+            (
+                Hedge::Slightly,
+                slightly(x.term("N")),
+                [0.928_318, 0.793_701, 0.584_804],
+            ),
+            (Hedge::Indeed, indeed(x.term("N")), [0.92, 0.5, 0.08]),
+        ] {
+            let text = format!("IF X IS {} N THEN Out IS A", hedge);
+            let (_, parsed, _) = parse_rule(&text, &registry).unwrap();
+            assert_eq!(parsed.to_string(), dsl.to_string());
+            let program = Program::compile([(&dsl, 1.)], Norm::Zadeh).unwrap();
+            for (crisp, value) in [1., 2.5, 4.].into_iter().zip(values) {
+                let finputs: HashMap<Term, f32> =
+                    fuzzy.fuzzify(crisp).unwrap().into_iter().collect();
+                let resolved = dsl.resolve(&finputs, Norm::Zadeh).unwrap();
+                assert!(
+                    (resolved - value).abs() < 1e-5,
+                    "{} at {} is {}",
+                    hedge,
+                    crisp,
+                    resolved
+                );
+                assert!((program.fire(&finputs).unwrap()[0] - value).abs() < 1e-5);
+            }
+        }
     }
 }
@@ -0,0 +1,98 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::{error::FuzzyError, mamdani::NoFiring, variables::Var};
+
+/// What a controller saw and did in the inferences it recorded here, for the
+/// graphs: the last crisp value of each input, the last aggregated output set,
+/// and how often inputs left their range or no rule fired.
+///
+/// It also keeps the last output for [`NoFiring::Hold`] and the buffers that
+/// make inference allocation-free, so the controllers themselves never change
+/// and one of them can drive any number of simulations, each with its own snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// Last crisp value of each input variable.
+    inputs: HashMap<Var, f32>,
+    /// How many crisp values of each input variable were outside its range.
+    outside: HashMap<Var, usize>,
+    /// The defuzzified point, then the aggregated output set over the normalized range.
+    pub(crate) aggregate: Vec<(f32, f32)>,
+    misfires: usize,
+    /// Last output that came from the rules.
+    held: f32,
+    /// Membership of every term, then the rest of the compiled program's slots.
+    pub(crate) values: Vec<f32>,
+    /// Whether each input variable was given a crisp value in this call.
+    pub(crate) given: Vec<bool>,
+    /// Accumulated strength of each output term.
+    pub(crate) acuts: Vec<f32>,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Last crisp value given for `var`, in its units.
+    pub fn input(&self, var: &Var) -> Option<f32> {
+        self.inputs.get(var).copied()
+    }
+
+    /// How many crisp values were outside the range of each input variable,
+    /// whatever its [`OutOfRange`](crate::fuzzy::OutOfRange) policy.
+    pub fn outside(&self) -> impl Iterator<Item = (&Var, usize)> {
+        self.outside.iter().map(|(var, &n)| (var, n))
+    }
+
+    /// The defuzzified point followed by the aggregated output set of the last
+    /// Mamdani inference that fired, over the normalized range. Empty until then,
+    /// and after rules fired into a set too narrow to be sampled.
+    pub fn aggregate(&self) -> &[(f32, f32)] {
+        &self.aggregate
+    }
+
+    /// How many times no rule fired.
+    pub fn misfires(&self) -> usize {
+        self.misfires
+    }
+
+    /// Records the crisp value of an input with universe `range`.
+    pub(crate) fn input_at(&mut self, var: &Var, x: f32, range: &Range<f32>) {
+        self.inputs.insert(var.clone(), x);
+        // Inserted even when inside, so that a later value outside does not allocate
+        *self.outside.entry(var.clone()).or_default() +=
+            usize::from(!(range.start..=range.end).contains(&x));
+    }
+
+    /// Makes room for `slots` values, `vars` input variables and `terms` output
+    /// terms sampled at `resolution` points.
+    pub(crate) fn reserve(&mut self, slots: usize, vars: usize, terms: usize, resolution: usize) {
+        self.values.resize(slots, 0.);
+        self.given.resize(vars, false);
+        self.acuts.resize(terms, 0.);
+        if terms > 0 {
+            self.aggregate.resize(resolution + 1, (0., 0.));
+        }
+    }
+
+    /// Last output that came from the rules, 0 before the first.
+    pub(crate) fn held(&self) -> f32 {
+        self.held
+    }
+
+    /// Counts misfires that were handled elsewhere, such as in a batch.
+    pub(crate) fn count_misfires(&mut self, n: usize) {
+        self.misfires += n;
+    }
+
+    pub(crate) fn record(&mut self, output: f32) -> Result<f32, FuzzyError> {
+        self.held = output;
+        Ok(output)
+    }
+
+    /// Output under `policy` when no rule fired, counting the misfire.
+    pub(crate) fn fallback(&mut self, policy: NoFiring) -> Result<f32, FuzzyError> {
+        self.misfires += 1;
+        policy.output(self.held)
+    }
+}
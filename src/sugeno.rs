@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::FuzzyError,
+    fuzzy::Fuzzy,
+    mamdani::{fuzzify_all, validate_rules, NoFiring},
+    program::{Program, Slots},
+    rules::{Norm, Rule},
+    snapshot::Snapshot,
+    variables::{Term, Var},
+};
+
+/// Crisp consequent of a Takagi-Sugeno-Kang rule.
+#[derive(Debug, Clone)]
+pub enum Consequent {
+    /// Zero-order: the rule always proposes the same value.
+    Constant(f32),
+    /// First-order: `sum(coeff * input) + offset` over the crisp inputs.
+    Linear(Vec<(Var, f32)>, f32),
+}
+
+impl Consequent {
+    pub fn eval(&self, crisp: &[(Var, f32)]) -> Result<f32, FuzzyError> {
+        match self {
+            Consequent::Constant(c) => Ok(*c),
+            Consequent::Linear(coeffs, offset) => coeffs.iter().try_fold(*offset, |acc, (i, k)| {
+                let (_, x) = crisp
+                    .iter()
+                    .find(|(j, _)| j == i)
+                    .ok_or_else(|| FuzzyError::MissingInput(i.clone()))?;
+                Ok(acc + k * x)
+            }),
+        }
+    }
+}
+
+pub struct Sugeno {
+    /// `(consequent, antecedent, weight)`, the weight in [0, 1] scaling the firing strength.
+    rules: Vec<(Consequent, Rule, f32)>,
+    /// The antecedents compiled with `norm`.
+    program: Program,
+    slots: Slots,
+    inputs: HashMap<Var, Fuzzy<Term>>,
+    /// For the ANDs and ORs of rules that do not choose their own.
+    norm: Norm,
+    no_firing: NoFiring,
+}
+
+impl Sugeno {
+    /// Checks the rule base against the memberships and the variables of the
+    /// linear consequents against the inputs, reporting every problem.
+    /// Uses Zadeh AND and OR, and holds the last output when no rule fires.
+    pub fn new(
+        rules: Vec<(Consequent, Rule, f32)>,
+        inputs: HashMap<Var, Fuzzy<Term>>,
+    ) -> Result<Self, Vec<FuzzyError>> {
+        let mut errors = validate_rules(rules.iter().map(|(_, r, w)| (r, *w)), &inputs);
+        for (out, _, _) in &rules {
+            if let Consequent::Linear(coeffs, _) = out {
+                for (i, _) in coeffs {
+                    let unknown = FuzzyError::UnknownConsequentInput(i.clone());
+                    if !inputs.contains_key(i) && !errors.contains(&unknown) {
+                        errors.push(unknown);
+                    }
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let norm = Norm::Zadeh;
+        let (program, slots) = Slots::compile(rules.iter().map(|(_, r, w)| (r, *w)), &inputs, norm)
+            .map_err(|e| vec![e])?;
+        Ok(Sugeno {
+            rules,
+            program,
+            slots,
+            inputs,
+            norm,
+            no_firing: NoFiring::Hold,
+        })
+    }
+
+    pub fn rules(&self) -> &[(Consequent, Rule, f32)] {
+        &self.rules
+    }
+
+    pub fn inputs(&self) -> &HashMap<Var, Fuzzy<Term>> {
+        &self.inputs
+    }
+
+    pub fn norm(&self) -> Norm {
+        self.norm
+    }
+
+    pub fn no_firing(&self) -> NoFiring {
+        self.no_firing
+    }
+
+    /// Sets what the controller outputs when no rule fires.
+    pub fn set_no_firing(&mut self, policy: NoFiring) {
+        self.no_firing = policy;
+    }
+
+    /// Changes the norm of the rules that do not choose their own, leaving the
+    /// controller as it was if the norm is invalid.
+    pub fn set_norm(&mut self, norm: Norm) -> Result<(), FuzzyError> {
+        if !norm.is_valid() {
+            return Err(FuzzyError::InvalidNorm(norm));
+        }
+        (self.program, self.slots) = Slots::compile(
+            self.rules.iter().map(|(_, r, w)| (r, *w)),
+            &self.inputs,
+            norm,
+        )?;
+        self.norm = norm;
+        Ok(())
+    }
+
+    pub fn fuzzify(&self, crisp: &[(Var, f32)]) -> Result<HashMap<Term, f32>, FuzzyError> {
+        fuzzify_all(&self.inputs, crisp)
+    }
+
+    /// Weighted average of the rule consequents, weighted by firing strength.
+    /// Records the inputs in `snapshot`, which also has the last output for
+    /// [`NoFiring::Hold`] and counts the misfires.
+    pub fn infer(&self, inputs: &[(Var, f32)], snapshot: &mut Snapshot) -> Result<f32, FuzzyError> {
+        snapshot.reserve(self.program.slots(), self.slots.vars(), 0, 0);
+        self.slots
+            .fill(&self.inputs, inputs, &self.program, snapshot)?;
+        let strengths = self.program.strengths(&snapshot.values);
+        let (mut num, mut den) = (0., 0.);
+        // Rules that did not fire are not evaluated, nor can they spoil the sum
+        for ((out, _, _), w) in self.rules.iter().zip(strengths).filter(|(_, w)| *w > 0.) {
+            num += w * out.eval(inputs)?;
+            den += w;
+        }
+        if den <= 0. {
+            return snapshot.fallback(self.no_firing);
+        }
+        snapshot.record(num / den)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::funcs::{smf, zmf};
+
+    fn x(term: &str) -> Term {
+        Var::new("X").term(term)
+    }
+
+    fn controller(p: Consequent) -> Result<Sugeno, Vec<FuzzyError>> {
+        let inputs = HashMap::from([(
+            Var::new("X"),
+            Fuzzy::new(
+                Var::new("X"),
+                HashMap::from([(x("N"), zmf(-10., 10.)), (x("P"), smf(-10., 10.))]),
+                -10.0..10.,
+            ),
+        )]);
+        let rules = vec![
+            (Consequent::Constant(10.), x("N").into(), 1.),
+            (p, x("P").into(), 1.),
+        ];
+        Sugeno::new(rules, inputs)
+    }
+
+    #[test]
+    fn averages_constants_by_strength() {
+        let s = controller(Consequent::Constant(90.)).unwrap();
+        let infer = |x| s.infer(&[(Var::new("X"), x)], &mut Snapshot::new());
+        assert_eq!(infer(-10.), Ok(10.));
+        assert_eq!(infer(10.), Ok(90.));
+        // N and P hold equally halfway
+        assert!((infer(0.).unwrap() - 50.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn evaluates_linear_consequents() {
+        let s = controller(Consequent::Linear(vec![(Var::new("X"), 2.)], 5.)).unwrap();
+        let infer = |x| s.infer(&[(Var::new("X"), x)], &mut Snapshot::new());
+        assert_eq!(infer(10.), Ok(25.));
+        let halfway = (10. + 5.) / 2.;
+        assert!((infer(0.).unwrap() - halfway).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rules_that_did_not_fire_are_not_evaluated() {
+        // P does not hold at all at the left end, so its infinite slope is never met
+        let s = controller(Consequent::Linear(vec![(Var::new("X"), f32::INFINITY)], 0.)).unwrap();
+        assert_eq!(
+            s.infer(&[(Var::new("X"), -10.)], &mut Snapshot::new()),
+            Ok(10.)
+        );
+    }
+
+    #[test]
+    fn linear_consequents_need_inputs() {
+        let errors = controller(Consequent::Linear(vec![(Var::new("Vx"), 1.)], 0.)).err();
+        assert_eq!(
+            errors,
+            Some(vec![FuzzyError::UnknownConsequentInput(Var::new("Vx"))])
+        );
+    }
+
+    #[test]
+    fn terms_are_defined_once() {
+        let temp = Var::new("Temp");
+        let cold = [
+            (temp.term("Cold"), zmf(0., 40.)),
+            (temp.term("Cold"), zmf(0., 20.)),
+        ];
+        let inputs = HashMap::from([(temp.clone(), Fuzzy::new(&temp, cold, 0.0..40.))]);
+        let rules = vec![(Consequent::Constant(10.), temp.term("Cold").into(), 1.)];
+        assert_eq!(
+            Sugeno::new(rules, inputs).err(),
+            Some(vec![FuzzyError::DuplicateTerm(
+                temp.clone(),
+                temp.term("Cold")
+            )])
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt::Display;
+
+use crate::{
+    rules::{Norm, Op, Ordinal, RuleNode},
+    variables::{Term, Var},
+};
+
+/// Why a controller produced its output, as returned by
+/// [`Mamdani::infer_explained`](crate::mamdani::Mamdani::infer_explained).
+#[derive(Debug, Clone)]
+pub struct Trace {
+    /// Name of the output variable.
+    pub variable: String,
+    pub inputs: Vec<InputTrace>,
+    pub rules: Vec<RuleTrace>,
+    /// Strength of each output term after accumulating the rules that conclude it.
+    pub outputs: Vec<(Term, f32)>,
+    /// Aggregated output set sampled over the output universe.
+    pub aggregate: Vec<(f32, f32)>,
+    /// Point picked by the defuzzifier, `None` if no rule fired.
+    pub defuzzified: Option<(f32, f32)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputTrace {
+    pub input: Var,
+    pub crisp: f32,
+    pub memberships: Vec<(Term, f32)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleTrace {
+    pub output: Term,
+    pub weight: f32,
+    /// Weighted firing strength.
+    pub strength: f32,
+    pub antecedent: NodeTrace,
+}
+
+/// Value of one node of a rule tree.
+#[derive(Debug, Clone)]
+pub struct NodeTrace {
+    pub node: RuleNode,
+    pub value: f32,
+    pub children: Vec<NodeTrace>,
+}
+
+impl Trace {
+    /// One sentence naming the strongest output term and the strongest rule behind it,
+    /// e.g. `Diff is PM (0.62) because Vx is P (0.90) and Th is N (0.70) and X is N (0.62)`.
+    pub fn explain(&self) -> String {
+        let best = self
+            .outputs
+            .iter()
+            .filter(|(_, a)| *a > 0.)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((output, strength)) = best else {
+            return format!("{}: no rule fired", self.variable);
+        };
+        let mut sentence = format!("{} is {} ({:.2})", self.variable, output, strength);
+        let rule = self
+            .rules
+            .iter()
+            .filter(|r| &r.output == output)
+            .max_by(|a, b| a.strength.total_cmp(&b.strength));
+        if let Some(rule) = rule {
+            sentence += &format!(" because {}", rule.antecedent.explain(None));
+            if rule.weight != 1. {
+                sentence += &format!(" (weight {:.2})", rule.weight);
+            }
+        }
+        sentence
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.explain())
+    }
+}
+
+impl NodeTrace {
+    /// Reads the sub-expression out as a clause. Chains of the same op and norm
+    /// are flattened, anything else nested under a binary op is parenthesised.
+    /// Norms other than Zadeh are named after the chain, e.g. `a and b, by product`.
+    fn explain(&self, parent: Option<&str>) -> String {
+        if let Some((hedges, leaf)) = self.hedged_leaf() {
+            return leaf_clause(leaf, &format!("is{}", hedges), self.value);
+        }
+        match &self.node {
+            RuleNode::Input(_) | RuleNode::Ordinal(..) => unreachable!("leaves are handled above"),
+            RuleNode::Hedge(h) => format!("{} ({})", h.name(), self.children[0].explain(None)),
+            RuleNode::Op(Op::Not) => match self.children[0].hedged_leaf() {
+                Some((hedges, leaf)) => leaf_clause(leaf, &format!("is not{}", hedges), self.value),
+                None => format!("not ({})", self.children[0].explain(None)),
+            },
+            RuleNode::Op(op) => {
+                let word = if let Op::And(_) = op { "and" } else { "or" };
+                let norm = op
+                    .norm()
+                    .filter(|&n| n != Norm::Zadeh)
+                    .map(|n| n.to_string().to_lowercase());
+                let key = match &norm {
+                    Some(n) => format!("{} by {}", word, n),
+                    None => word.to_string(),
+                };
+                let mut clause = self
+                    .children
+                    .iter()
+                    .map(|c| c.explain(Some(&key)))
+                    .collect::<Vec<_>>()
+                    .join(&format!(" {} ", word));
+                if parent == Some(key.as_str()) {
+                    return clause;
+                }
+                if let Some(n) = norm {
+                    clause += &format!(", by {}", n);
+                }
+                match parent {
+                    Some(_) => format!("({})", clause),
+                    None => clause,
+                }
+            }
+        }
+    }
+
+    /// The words of the hedges above a leaf, outermost first, and the leaf itself.
+    fn hedged_leaf(&self) -> Option<(String, &RuleNode)> {
+        match &self.node {
+            RuleNode::Input(_) | RuleNode::Ordinal(..) => Some((String::new(), &self.node)),
+            RuleNode::Hedge(h) => {
+                let (hedges, leaf) = self.children[0].hedged_leaf()?;
+                Some((format!(" {}{}", h.name(), hedges), leaf))
+            }
+            RuleNode::Op(_) => None,
+        }
+    }
+}
+
+fn leaf_clause(leaf: &RuleNode, verb: &str, value: f32) -> String {
+    match leaf {
+        RuleNode::Input(i) => clause(i, verb, value),
+        RuleNode::Ordinal(o, _) => ordinal(o, verb, value),
+        _ => unreachable!("not a leaf"),
+    }
+}
+
+fn clause(input: &Term, verb: &str, value: f32) -> String {
+    format!("{} {} {} ({:.2})", input.var(), verb, input, value)
+}
+
+fn ordinal(ordinal: &Ordinal, verb: &str, value: f32) -> String {
+    let condition = match ordinal {
+        Ordinal::AtLeast(t) => format!("at least {}", t),
+        Ordinal::AtMost(t) => format!("at most {}", t),
+        Ordinal::Between(a, b) => format!("between {} and {}", a, b),
+    };
+    format!("{} {} {} ({:.2})", ordinal.var(), verb, condition, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        funcs::tri,
+        fuzzy::Fuzzy,
+        mamdani::{Mamdani, NoFiring},
+        snapshot::Snapshot,
+        variables::Var,
+    };
+
+    /// Explains `rules` at Vx 0.9, Th -0.7 and X -0.62, where Vx is P (0.90),
+    /// Th is N (0.70) and X is N (0.62) or Z (0.38).
+    fn explain(rules: &str) -> String {
+        let even = |name: &str| {
+            let var = Var::new(name);
+            let terms = [
+                (var.term("N"), tri(-2., -1., 0.)),
+                (var.term("Z"), tri(-1., 0., 1.)),
+                (var.term("P"), tri(0., 1., 2.)),
+            ];
+            (var.clone(), Fuzzy::new(&var, terms, -1.0..1.))
+        };
+        let diff = Var::new("Diff");
+        let outputs = [
+            (diff.term("NS"), tri(-2., -1., 1.)),
+            (diff.term("PM"), tri(-1., 1., 2.)),
+        ];
+        let m = Mamdani::builder()
+            .inputs([even("Vx"), even("Th"), even("X")])
+            .output(Fuzzy::new(&diff, outputs, -1.0..1.))
+            .text_rules(rules)
+            .text_rules("IF Vx IS N THEN Diff IS NS")
+            .no_firing(NoFiring::Default(0.))
+            .build()
+            .unwrap();
+        let inputs = [
+            (Var::new("Vx"), 0.9),
+            (Var::new("Th"), -0.7),
+            (Var::new("X"), -0.62),
+        ];
+        let (_, trace) = m.infer_explained(&inputs, &mut Snapshot::new()).unwrap();
+        trace.explain()
+    }
+
+    #[test]
+    fn chains_of_one_op_are_flattened() {
+        assert_eq!(
+            explain("IF Vx IS P AND Th IS N AND X IS N THEN Diff IS PM"),
+            "Diff is PM (0.62) because Vx is P (0.90) and Th is N (0.70) and X is N (0.62)"
+        );
+    }
+
+    #[test]
+    fn mixed_ops_are_parenthesised() {
+        assert_eq!(
+            explain("IF Vx IS P AND (Th IS N OR X IS P) THEN Diff IS PM"),
+            "Diff is PM (0.70) because Vx is P (0.90) and (Th is N (0.70) or X is P (0.00))"
+        );
+    }
+
+    #[test]
+    fn other_norms_are_named() {
+        assert_eq!(
+            explain("IF Vx IS P AND Th IS N THEN Diff IS PM USING PRODUCT"),
+            "Diff is PM (0.63) because Vx is P (0.90) and Th is N (0.70), by product"
+        );
+    }
+
+    #[test]
+    fn negations_hedges_and_ordinals_read_as_words() {
+        assert_eq!(
+            explain("IF Vx IS NOT VERY N AND X IS AT LEAST Z THEN Diff IS PM"),
+            "Diff is PM (0.38) because Vx is not very N (1.00) and X is at least Z (0.38)"
+        );
+    }
+
+    #[test]
+    fn weights_are_named() {
+        assert_eq!(
+            explain("IF Th IS N THEN Diff IS PM WITH 0.5"),
+            "Diff is PM (0.35) because Th is N (0.70) (weight 0.50)"
+        );
+    }
+
+    #[test]
+    fn misfires_say_so() {
+        assert_eq!(explain("IF X IS P THEN Diff IS PM"), "Diff: no rule fired");
+    }
+}
@@ -1,4 +1,4 @@
-use std::{f32::consts::PI, fmt::Display, ops::Range, rc::Rc};
+use std::{f32::consts::PI, fmt::Display, hash::Hash, ops::Range};
 
 use egui::{
     epaint::Shadow,
@@ -13,10 +13,16 @@ use egui_macroquad::egui::{
 use macroquad::prelude::*;
 use macroquad_particles::{ColorCurve, Curve};
 
-use crate::bezier;
+use crate::{
+    bezier,
+    funcs::Mf,
+    fuzzy::{Defuzzifier, Fuzzy},
+};
+#[derive(Clone)]
 pub struct Graph {
     title: String,
-    pub funcs: Vec<(String, Rc<dyn Fn(f32) -> f32>)>,
+    unit: String,
+    pub funcs: Vec<(String, Mf)>,
     colors: Vec<Color32>,
     range: Range<f32>,
     lines: Vec<Vec<[f64; 2]>>,
@@ -25,12 +31,13 @@ pub struct Graph {
 impl Graph {
     pub fn new(
         title: String,
-        funcs: Vec<(String, Rc<dyn Fn(f32) -> f32>)>,
+        funcs: Vec<(String, Mf)>,
         colors: Option<Vec<Color32>>,
         range: Option<Range<f32>>,
     ) -> Self {
         Graph {
             title,
+            unit: String::new(),
             colors: colors.unwrap_or_else(|| {
                 [
                     Color32::LIGHT_BLUE,
@@ -51,7 +58,7 @@ impl Graph {
                     (0..=100)
                         .map(|i| {
                             let x = egui::remap(i as f64, 0.0..=100f64, -0.0..=1.);
-                            [x, f(x as f32) as f64]
+                            [x, f.eval(x as f32) as f64]
                         })
                         .collect()
                 })
@@ -60,15 +67,86 @@ impl Graph {
         }
     }
 
-    pub fn draw(
+    /// Graph of the membership functions of `fuzzy`, labelled in its units.
+    pub fn of<V: Eq + Hash + Clone + Display>(fuzzy: &Fuzzy<V>) -> Self {
+        let funcs = fuzzy
+            .terms()
+            .iter()
+            .map(|t| t.to_string())
+            .zip(fuzzy.functions().iter().cloned())
+            .collect();
+        let mut graph = Graph::new(
+            fuzzy.name().to_string(),
+            funcs,
+            None,
+            Some(fuzzy.range().clone()),
+        );
+        graph.set_unit(fuzzy.unit());
+        graph
+    }
+
+    pub fn set_unit(&mut self, unit: &str) {
+        self.unit = unit.to_string();
+    }
+
+    /// Graph of the input `fuzzy` with a marker at `x`, if it had a value yet.
+    /// Returns the membership of `x` in each term as `fuzzy` fuzzifies it, out
+    /// of range values included, or zeros if its policy rejects `x`.
+    pub fn draw_input<V: Eq + Hash + Clone + Display>(
         &self,
         ctx: &egui::Context,
         pos: (f32, f32),
         size: (f32, f32),
-        inp: Option<f32>,
-        out: Option<&Vec<(f32, f32)>>,
+        fuzzy: &Fuzzy<V>,
+        x: Option<f32>,
     ) -> Vec<f32> {
-        let mut memberships = vec![0.; self.funcs.len()];
+        let mut memberships = vec![0.; fuzzy.terms().len()];
+        let fuzzified = x.is_some_and(|x| fuzzy.memberships(x, &mut memberships).is_ok());
+        let inp = x.map(|x| (x, fuzzified.then_some(&memberships[..])));
+        self.draw(ctx, pos, size, inp, None::<(&[(f32, f32)], Defuzzifier)>);
+        memberships
+    }
+
+    /// Graph of an output with the `aggregate` of a
+    /// [`Snapshot`](crate::snapshot::Snapshot) and the point `method` picked on it.
+    pub fn draw_output(
+        &self,
+        ctx: &egui::Context,
+        pos: (f32, f32),
+        size: (f32, f32),
+        aggregate: &[(f32, f32)],
+        method: Defuzzifier,
+    ) {
+        self.draw(ctx, pos, size, None, Some((aggregate, method)));
+    }
+
+    /// Axis label for `x` in the units of the range.
+    fn label(&self, x: f32) -> String {
+        // Round away float noise such as 0.30000001
+        let x = (x * 1000.).round() / 1000.;
+        if self.unit.is_empty() {
+            x.to_string()
+        } else {
+            format!("{} {}", x, self.unit)
+        }
+    }
+
+    /// Takes `inp` in the units of the range with the memberships to mark on
+    /// the terms, and `out` as the defuzzified point followed by the output set,
+    /// both normalized.
+    pub fn draw(
+        &self,
+        ctx: &egui::Context,
+        pos: (f32, f32),
+        size: (f32, f32),
+        inp: Option<(f32, Option<&[f32]>)>,
+        out: Option<(&[(f32, f32)], impl Display)>,
+    ) {
+        let inp = inp.map(|(x, mu)| {
+            let x = (x - self.range.start) / (self.range.end - self.range.start);
+            (x, mu)
+        });
+        let out = out.filter(|(out, _)| !out.is_empty());
         egui::Window::new(&self.title)
             .frame(Frame {
                 inner_margin: egui::Margin::same(0.),
@@ -104,40 +182,51 @@ impl Graph {
                             .position(egui::plot::Corner::RightTop),
                     )
                     .show(ui, |plot_ui| {
-                        plot_ui.text(
-                            Text::new(
-                                [0.02, 0.05].into(),
-                                RichText::new(self.range.start.to_string())
-                                    .color(Color32::WHITE)
-                                    .font(FontId::proportional(12.0)),
-                            )
-                            .color(Color32::WHITE),
-                        );
-                        plot_ui.text(
-                            Text::new(
-                                [0.98, 0.05].into(),
-                                RichText::new(self.range.end.to_string())
-                                    .color(Color32::WHITE)
-                                    .font(FontId::proportional(12.0)),
-                            )
-                            .color(Color32::WHITE),
-                        );
-                        if let Some(x) = inp {
-                            plot_ui.vline(
-                                VLine::new(x.clamp(0., 1.)).color(Color32::GREEN).width(1.5),
+                        let span = self.range.end - self.range.start;
+                        let ends = [(0.02, self.range.start), (0.98, self.range.end)];
+                        let ticks = ticks(&self.range)
+                            .into_iter()
+                            .map(|x| (((x - self.range.start) / span) as f64, x));
+                        for (at, _) in ticks.clone() {
+                            plot_ui.line(
+                                Line::new(vec![[at, 0.], [at, 0.03]])
+                                    .width(1.)
+                                    .color(Color32::WHITE),
+                            );
+                        }
+                        // Tick labels close to an end would overlap its label
+                        let labels = ticks.filter(|(at, _)| (0.12..=0.88).contains(at));
+                        for (at, x) in ends.into_iter().chain(labels) {
+                            plot_ui.text(
+                                Text::new(
+                                    [at, 0.06].into(),
+                                    RichText::new(self.label(x))
+                                        .color(Color32::WHITE)
+                                        .font(FontId::proportional(12.0)),
+                                )
+                                .color(Color32::WHITE),
                             );
                         }
-                        for (i, (_, f)) in self.funcs.iter().enumerate() {
+                        if let Some((x, _)) = inp {
+                            // Red when the input is past the end of the range
+                            let color = if (0. ..=1.).contains(&x) {
+                                Color32::GREEN
+                            } else {
+                                Color32::RED
+                            };
+                            plot_ui.vline(VLine::new(x.clamp(0., 1.)).color(color).width(1.5));
+                        }
+                        for i in 0..self.funcs.len() {
                             plot_ui.line(
                                 Line::new(self.lines[i].clone())
                                     .width(2.)
                                     .color(self.colors[i])
                                     .name(&self.funcs[i].0),
                             );
-                            if let Some(x) = inp {
-                                memberships[i] = f(x);
+                            // As the controller fuzzified it, after the out-of-range policy
+                            if let Some((x, Some(mu))) = inp {
                                 plot_ui.points(
-                                    Points::new([x.clamp(0., 1.) as f64, f(x) as f64])
+                                    Points::new([x.clamp(0., 1.) as f64, mu[i] as f64])
                                         // .name(format!("Hello"))
                                         .filled(true)
                                         .radius(4.)
@@ -145,7 +234,7 @@ impl Graph {
                                 )
                             }
                         }
-                        if let Some(out) = out {
+                        if let Some((out, method)) = out {
                             plot_ui.line(
                                 Line::new(
                                     out.iter()
@@ -165,7 +254,7 @@ impl Graph {
                             );
                             plot_ui.points(
                                 Points::new([out[0].0 as f64, out[0].1 as f64])
-                                    // .name(format!("Hello"))
+                                    .name(method)
                                     .filled(true)
                                     .radius(6.)
                                     .color(Color32::GREEN)
@@ -175,10 +264,24 @@ impl Graph {
                     })
                     .response
             });
-        memberships
     }
 }
 
+/// Round values inside `range` spaced 1, 2 or 5 times a power of ten apart,
+/// at most about five of them.
+fn ticks(range: &Range<f32>) -> Vec<f32> {
+    let rough = (range.end - range.start) / 5.;
+    let magnitude = 10f32.powf(rough.log10().floor());
+    let step = [1., 2., 5., 10.]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|&s| s >= rough)
+        .unwrap_or(rough);
+    let first = (range.start / step).ceil() as i32;
+    let last = (range.end / step).floor() as i32;
+    (first..=last).map(|i| i as f32 * step).collect()
+}
+
 pub fn smoke() -> macroquad_particles::EmitterConfig {
     macroquad_particles::EmitterConfig {
         lifetime: 0.8,
@@ -257,6 +360,7 @@ pub fn draw_rules(
         &[impl Display],
         &[impl Display],
     ),
+    weights: &[f32],
     fuzzied: (&[f32], &[f32], Option<f32>),
 ) {
     // make a grid table of size.0 * size.1 with each square of edge spacing
@@ -287,10 +391,9 @@ pub fn draw_rules(
             let m = measure_text(&format!("{}", labels.2[i * size.1 + j]), None, 24, 1.);
             let font = Font::default();
             font.set_filter(FilterMode::Nearest);
-            let c = (fuzzied.0[i]
-                .min(fuzzied.1[j])
-                .min(fuzzied.2.unwrap_or_else(|| 1.))
-                * 255.) as u8;
+            let weight = weights.get(i * size.1 + j).copied().unwrap_or(1.);
+            let c =
+                (fuzzied.0[i].min(fuzzied.1[j]).min(fuzzied.2.unwrap_or(1.)) * weight * 255.) as u8;
 
             draw_text_ex(
                 &format!("{}", labels.2[i * size.1 + j]),
@@ -304,6 +407,17 @@ pub fn draw_rules(
                     ..Default::default()
                 },
             );
+            if weight != 1. {
+                let text = format!("{:.2}", weight);
+                let m = measure_text(&text, None, 12, 1.);
+                draw_text(
+                    &text,
+                    (x + (i as f32 + 1.) * spacing - m.width - 3.).round(),
+                    (y + (j as f32 + 1.) * spacing - 3.).round(),
+                    12.,
+                    GRAY,
+                );
+            }
         }
     }
     for i in 0..labels.3.len() {
@@ -377,3 +491,30 @@ pub fn draw_title(ctx: &egui::Context) {
             )
         });
 }
+
+pub fn draw_explanations(ctx: &egui::Context, pos: (f32, f32), width: f32, lines: &[String]) {
+    egui::Window::new("Why")
+        .frame(Frame {
+            inner_margin: egui::Margin::same(6.),
+            outer_margin: egui::Margin::same(0.),
+            rounding: egui::Rounding::none(),
+            fill: Color32::from_black_alpha(160),
+            shadow: Shadow::NONE,
+            stroke: egui::Stroke::new(2., Color32::WHITE),
+        })
+        .current_pos(pos)
+        .fixed_size((width, 0.))
+        .resizable(false)
+        .movable(false)
+        .collapsible(false)
+        .title_bar(false)
+        .show(ctx, |ui| {
+            for line in lines {
+                ui.label(
+                    RichText::new(line)
+                        .color(Color32::WHITE)
+                        .font(FontId::proportional(14.0)),
+                );
+            }
+        });
+}
@@ -0,0 +1,146 @@
+use std::{borrow::Borrow, fmt::Display, sync::Arc};
+
+use crate::error::FuzzyError;
+
+/// Name of a linguistic variable, e.g. `X` or `Diff`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Var(Arc<str>);
+
+impl Var {
+    pub fn new(name: &str) -> Self {
+        Var(name.into())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    pub fn term(&self, name: &str) -> Term {
+        Term {
+            var: self.clone(),
+            name: name.into(),
+        }
+    }
+}
+
+impl Borrow<str> for Var {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Var {
+    fn from(name: &str) -> Self {
+        Var::new(name)
+    }
+}
+
+impl Display for Var {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A named term of a linguistic variable, e.g. `N` of `X`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Term {
+    var: Var,
+    name: Arc<str>,
+}
+
+impl Term {
+    pub fn new(var: impl Into<Var>, name: &str) -> Self {
+        var.into().term(name)
+    }
+
+    pub fn var(&self) -> &Var {
+        &self.var
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// A variable with its terms, in order from lowest to highest.
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub var: Var,
+    pub terms: Vec<Term>,
+}
+
+impl Variable {
+    pub fn position(&self, term: &Term) -> Option<usize> {
+        self.terms.iter().position(|t| t == term)
+    }
+}
+
+/// Variables and their terms known at runtime, used to resolve names in text rules.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    variables: Vec<Variable>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a variable whose terms are ordered as given.
+    pub fn define(
+        &mut self,
+        name: impl Into<Var>,
+        terms: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Var, FuzzyError> {
+        let var = name.into();
+        if self.get(var.name()).is_some() {
+            return Err(FuzzyError::DuplicateVariable(var));
+        }
+        self.variables.push(Variable {
+            terms: terms.into_iter().map(|t| var.term(t.as_ref())).collect(),
+            var: var.clone(),
+        });
+        Ok(var)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Variable> {
+        self.variables.iter().find(|v| v.var.name() == name)
+    }
+
+    pub fn term(&self, var: &str, term: &str) -> Option<Term> {
+        self.get(var)?
+            .terms
+            .iter()
+            .find(|t| t.name() == term)
+            .cloned()
+    }
+
+    pub fn variables(&self) -> &[Variable] {
+        &self.variables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registries_resolve_defined_names() {
+        let mut registry = Registry::new();
+        let x = registry.define("X", ["N", "P"]).unwrap();
+        assert_eq!(registry.term("X", "P"), Some(x.term("P")));
+        assert_eq!(registry.term("X", "Z"), None);
+        assert_eq!(registry.term("Y", "N"), None);
+        assert_eq!(
+            registry.define("X", ["Z"]),
+            Err(FuzzyError::DuplicateVariable(x))
+        );
+        assert_eq!(registry.variables().len(), 1);
+    }
+}